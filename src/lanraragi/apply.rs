@@ -0,0 +1,170 @@
+use super::archive::Archive;
+use super::commands::{accept_candidate, export_untranslated, load_dictionary};
+use super::config::Settings;
+use super::eh::GL;
+use super::store::{mark_review_applied, Decision};
+
+use owo_colors::OwoColorize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tabled::{builder::Builder, settings::Style};
+
+/// 批量写入文件中的一行
+pub struct ApplyRow {
+    /// 文件中的行号
+    pub line: usize,
+    pub arcid: String,
+    pub url: String,
+}
+
+/// 读取 `arcid,url` 格式的CSV，`-` 表示标准输入；表头和 `#` 开头的行忽略
+pub fn read_rows(path: &Path) -> Result<Vec<ApplyRow>, String> {
+    let mut input: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(File::open(path).map_err(|e| format!("无法打开 {}: {}", path.display(), e))?)
+    };
+    let mut text = String::new();
+    input
+        .read_to_string(&mut text)
+        .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+    let mut rows = vec![];
+    // 逐行解析：csv 跳过空行和注释后，下一条记录的行号会停在被跳过的行
+    for (i, text_line) in text.lines().enumerate() {
+        let trimmed = text_line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let record = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(text_line.as_bytes())
+            .records()
+            .next()
+            .unwrap_or_else(|| Ok(csv::StringRecord::new()))
+            .map_err(|e| format!("读取 {} 第 {} 行失败: {}", path.display(), i + 1, e))?;
+        let field = |i| record.get(i).unwrap_or_default().to_string();
+        if rows.is_empty() && field(0).eq_ignore_ascii_case("arcid") {
+            continue;
+        }
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        rows.push(ApplyRow {
+            line: i + 1,
+            arcid: field(0),
+            url: field(1),
+        });
+    }
+    Ok(rows)
+}
+
+/// 检查一行并取得作品和画廊
+async fn validate(
+    settings: &Settings,
+    row: &ApplyRow,
+    seen: &mut HashMap<String, usize>,
+) -> Result<(Archive, GL), String> {
+    if row.arcid.is_empty() {
+        return Err("缺少arcid".to_string());
+    }
+    if let Some(line) = seen.insert(row.arcid.clone(), row.line) {
+        return Err(format!("arcid重复，见第 {} 行", line));
+    }
+    let gl = GL::from_url(&row.url).ok_or_else(|| format!("无效的画廊URL: {}", row.url))?;
+    let archive = Archive::fetch_archive(settings, &row.arcid)
+        .await
+        .map_err(|e| format!("找不到作品: {}", e))?;
+    Ok((archive, gl))
+}
+
+/// 不搜索也不询问，直接把各行画廊的标签写入对应作品，最后打印每行的结果
+pub async fn apply_galleries(
+    settings: &Settings,
+    rows: &[ApplyRow],
+    decision: Decision,
+) -> Result<(), String> {
+    settings.require(true)?;
+    if rows.is_empty() {
+        return Err("没有需要处理的行".to_string());
+    }
+    let (_, tag_cn) = load_dictionary(settings).await?;
+    let mut seen = HashMap::new();
+    let mut report = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        let result = match validate(settings, row, &mut seen).await {
+            Ok((archive, gl)) => {
+                let result = accept_candidate(settings, &archive, &gl, &tag_cn, None, decision)
+                    .await
+                    .map_err(|e| format!("写入失败: {}", e));
+                if result.is_ok() {
+                    mark_review_applied(&archive.arcid);
+                }
+                (archive.title, result)
+            }
+            Err(err) => (String::new(), Err(err)),
+        };
+        println!("已处理 {}/{}", (i + 1).green(), rows.len().cyan());
+        report.push((row, result));
+    }
+    export_untranslated();
+
+    let mut builder = Builder::default();
+    builder.push_record(["行", "arcid", "标题", "结果"]);
+    for (row, (title, result)) in &report {
+        let result = match result {
+            Ok(()) => "✅ 已写入".green().to_string(),
+            Err(err) => format!("❌ {}", err).red().to_string(),
+        };
+        builder.push_record([
+            row.line.to_string(),
+            row.arcid.clone(),
+            title.clone(),
+            result,
+        ]);
+    }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    println!("{}", table);
+
+    let failed = report.iter().filter(|(_, (_, r))| r.is_err()).count();
+    println!(
+        "成功 {} 行，失败 {} 行",
+        (report.len() - failed).bright_green(),
+        failed.bright_red()
+    );
+    if failed > 0 {
+        Err(format!("{} 行没有写入", failed))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_rows_skips_header_comments_and_blanks() {
+        let path = std::env::temp_dir().join("lantag_c_test_apply.csv");
+        std::fs::write(
+            &path,
+            "arcid,url\n# 注释\n\n abc , https://e-hentai.org/g/1/a/ \ndef\n,\n",
+        )
+        .unwrap();
+        let rows = read_rows(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|row| (row.line, row.arcid.as_str(), row.url.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            [(4, "abc", "https://e-hentai.org/g/1/a/"), (5, "def", "")]
+        );
+        assert!(read_rows(Path::new("/nonexistent/lantag_c.csv")).is_err());
+    }
+}
@@ -4,12 +4,13 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::sync::OnceLock;
 
-use super::progress::make_progress_bar;
+use super::config::Settings;
+use super::error::FetchError;
+use super::progress::{log, make_progress_bar};
+use super::store::record_write;
 use super::utils::fetch_raw_with_retry;
 
-use super::args::args;
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Archive {
     pub arcid: String,
@@ -29,60 +30,76 @@ fn regex_title_obj() -> &'static Regex {
     ).unwrap())
 }
 
+/// 拆分 LANraragi 的逗号分隔标签
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
 impl Archive {
-    pub async fn change_tags_to_lanraragi(&self, tags: &str) {
+    pub async fn change_tags_to_lanraragi(
+        &self,
+        settings: &Settings,
+        tags: &str,
+    ) -> Result<(), FetchError> {
         let url = format!(
             "http://{}/api/archives/{}/metadata",
-            &args().endpoint,
-            &self.arcid
+            &settings.endpoint, &self.arcid
         );
         // put method with form data
         let form_data = vec![
             ("tags", tags),
             ("title", &self.title),
-            ("key", &args().api_key),
+            ("key", &settings.api_key),
         ];
         // no need to re-try
         let resp = reqwest::Client::new()
             .put(url)
             .form(&form_data)
             .send()
-            .await
-            .unwrap();
+            .await?;
         if resp.status().is_success() {
-            println!("to -> {}", tags.bright_cyan());
+            record_write(self, tags);
+            log(format!("to -> {}", tags.bright_cyan()));
+            Ok(())
         } else {
-            for line in resp.text().await.unwrap().lines() {
-                println!("{}", line.red());
-            }
-            panic!("failed")
+            Err(FetchError::Other(format!(
+                "HTTP {}: {}",
+                resp.status(),
+                resp.text().await?.trim()
+            )))
         }
     }
 
-    pub fn is_empty_tags(&self) -> bool {
-        let tag_list: Vec<&str> = self.tags.split(',').collect();
-        if tag_list.is_empty() {
-            return true;
-        }
-        tag_list.len() == 1 && tag_list[0].starts_with("date_added")
+    pub fn tag_list(&self) -> Vec<String> {
+        split_tags(&self.tags)
     }
 
+    /// `date_added` 标签记录的添加时间，没有时为0
+    pub fn date_added(&self) -> i64 {
+        self.tag_list()
+            .iter()
+            .find_map(|tag| tag.strip_prefix("date_added:")?.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// 从标题中提取搜索关键词，会在后台调用，不输出
     pub fn regex_title(&self) -> String {
         if let Some(captures) = regex_title_obj().captures(&self.title) {
             if let Some(group) = captures.get(1) {
                 if group.as_str().chars().count() == 1 {
                     let start_pos = group.start();
-                    let remaining_title = &self.title[start_pos + group.as_str().bytes().count()..];
+                    let remaining_title = &self.title[start_pos + group.as_str().len()..];
                     if let Some(captures) = regex_title_obj().captures(remaining_title) {
                         if let Some(group) = captures.get(1) {
                             if group.as_str().chars().count() > 1 {
-                                println!("match group: {}", group.as_str().bright_yellow());
                                 return group.as_str().to_string();
                             }
                         }
                     }
                 }
-                println!("match group: {}", group.as_str().bright_yellow());
                 return group.as_str().to_string();
             }
         }
@@ -90,13 +107,24 @@ impl Archive {
     }
 
     // 异步函数，获取所有lanraragi作品
-    pub async fn fetch_archives() -> Vec<Self> {
+    pub async fn fetch_archives(settings: &Settings) -> Vec<Self> {
         let resp = fetch_raw_with_retry(|| {
-            reqwest::Client::new().get(format!("http://{}/api/archives", &args().endpoint))
+            reqwest::Client::new().get(format!("http://{}/api/archives", &settings.endpoint))
         })
         .await
         .unwrap();
         serde_json::from_slice::<Vec<Self>>(&make_progress_bar(resp, "lanraragi").await.unwrap())
             .unwrap()
     }
+
+    pub async fn fetch_archive(settings: &Settings, arcid: &str) -> Result<Self, FetchError> {
+        let resp = fetch_raw_with_retry(|| {
+            reqwest::Client::new().get(format!(
+                "http://{}/api/archives/{}/metadata",
+                &settings.endpoint, arcid
+            ))
+        })
+        .await?;
+        Ok(resp.json::<Self>().await?)
+    }
 }
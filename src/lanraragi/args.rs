@@ -1,26 +1,418 @@
-use clap::Parser;
-use std::sync::OnceLock;
+use clap::builder::FalseyValueParser;
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use super::tag::DbFormat;
+use super::unhandle::Reason;
 
 /// 为Lanraragi的作品增添中文标签，仅限无标签作品
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None, styles=get_styles())]
-pub struct Args {
+pub struct Cli {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// 不指定子命令时即为 `run`
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 所有子命令共用的连接和词典参数
+#[derive(ClapArgs, Debug, Default, Clone)]
+pub struct CommonArgs {
+    /// 配置文件，默认为 `~/.config/lantag_c/config.toml`
+    #[arg(long, global = true, env = "LANTAG_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// 使用配置文件中的哪个profile
+    #[arg(short, long, global = true, env = "LANTAG_PROFILE")]
+    pub profile: Option<String>,
+
     /// Lanraragi的URL;例 192.168.0.1:3000
-    #[arg(short, long)]
-    pub endpoint: String,
+    #[arg(short, long, global = true, env = "LANTAG_ENDPOINT")]
+    pub endpoint: Option<String>,
 
-    /// Lanraragi的API key
-    #[arg(short, long)]
-    pub api_key: String,
+    /// Lanraragi的API key；没有在命令行给出时依次读取环境变量
+    /// LANTAG_API_KEY、LANTAG_API_KEY_FILE 和 profile
+    #[arg(short, long, global = true)]
+    pub api_key: Option<String>,
+
+    /// 从文件读取Lanraragi的API key
+    #[arg(long, global = true)]
+    pub api_key_file: Option<PathBuf>,
 
     /// EX的Cookies;格式为：`igneous=xxx; ipb_member_id=xxx; ipb_pass_hash=xxx`
+    ///
+    /// 没有在命令行给出时依次读取环境变量 LANTAG_COOKIES、LANTAG_COOKIES_FILE 和 profile
+    #[arg(short, long, global = true)]
+    pub cookies: Option<String>,
+
+    /// 从文件读取EX的Cookies，支持上面的格式或Netscape的cookies.txt
+    #[arg(long, global = true)]
+    pub cookies_file: Option<PathBuf>,
+
+    /// 搜索使用的站点;例 exhentai.org
+    #[arg(long, global = true, env = "LANTAG_SITE")]
+    pub site: Option<String>,
+
+    /// EhTagTranslation数据库的格式
+    #[arg(long, global = true, value_enum, env = "LANTAG_DB_FORMAT")]
+    pub db_format: Option<DbFormat>,
+
+    /// 本地覆盖词典(TOML/JSON)，优先于EhTagTranslation
+    #[arg(long, global = true, env = "LANTAG_OVERRIDES")]
+    pub overrides: Option<PathBuf>,
+
+    /// 最高评分不低于该值(0~1)时自动选择
+    #[arg(long, global = true, env = "LANTAG_AUTO")]
+    pub auto: Option<f64>,
+
+    /// 有效标签少于该数量时算作无标签，默认1
+    #[arg(long, global = true, env = "LANTAG_UNTAGGED_MIN_TAGS")]
+    pub untagged_min_tags: Option<usize>,
+
+    /// 不算作有效标签的namespace，替换配置文件中的列表;例 date_added,uploader
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        env = "LANTAG_UNTAGGED_IGNORE_NAMESPACE"
+    )]
+    pub untagged_ignore_namespace: Vec<String>,
+
+    /// 写入标签后把作品加入该分类，不存在时创建
+    #[arg(long, global = true, env = "LANTAG_TAGGED_CATEGORY")]
+    pub tagged_category: Option<String>,
+
+    /// 把未处理的作品加入该分类，不存在时创建
+    #[arg(long, global = true, env = "LANTAG_UNHANDLED_CATEGORY")]
+    pub unhandled_category: Option<String>,
+
+    /// 写入标签后清除作品的新作品标记
+    #[arg(
+        long,
+        global = true,
+        env = "LANTAG_CLEAR_NEW",
+        num_args = 0,
+        default_missing_value = "true",
+        value_parser = FalseyValueParser::new(),
+        overrides_with = "no_clear_new"
+    )]
+    pub clear_new: Option<bool>,
+    /// 本次不清除新作品标记，优先于环境变量和配置文件
+    #[arg(long, global = true, overrides_with = "clear_new")]
+    pub no_clear_new: bool,
+
+    /// 搜索失败时依次尝试的LANraragi元数据插件(namespace);例 ehplugin,hdoujinplugin
+    #[arg(long, global = true, value_delimiter = ',', env = "LANTAG_PLUGINS")]
+    pub plugins: Vec<String>,
+
+    /// 标题搜索没有结果时，用作品前几页图片的SHA-1在EH上搜索
+    #[arg(
+        long,
+        global = true,
+        env = "LANTAG_HASH_SEARCH",
+        num_args = 0,
+        default_missing_value = "true",
+        value_parser = FalseyValueParser::new(),
+        overrides_with = "no_hash_search"
+    )]
+    pub hash_search: Option<bool>,
+    /// 本次不使用哈希搜索，优先于环境变量和配置文件
+    #[arg(long, global = true, overrides_with = "hash_search")]
+    pub no_hash_search: bool,
+
+    /// EH请求的最小间隔(毫秒)，默认500
+    #[arg(long, global = true, env = "LANTAG_REQUEST_INTERVAL")]
+    pub request_interval: Option<u64>,
+
+    /// 输出合并后的词典并退出
+    #[arg(long, global = true)]
+    pub print_effective_dict: bool,
+}
+
+/// `run` 和 `retry` 的交互参数
+#[derive(ClapArgs, Debug, Default, Clone)]
+pub struct RunArgs {
+    /// 选择时显示候选作品标签的中文说明
+    #[arg(long)]
+    pub show_intro: bool,
+    /// 询问时在后台提前搜索的作品数
+    #[arg(long, default_value_t = 3)]
+    pub prefetch: usize,
+
+    /// 使用全屏界面选择候选作品
+    #[arg(long)]
+    pub tui: bool,
+    /// 继续上次中断的运行
+    #[arg(long)]
+    pub resume: bool,
+
+    /// 不询问，只写入达到自动选择阈值的候选，其余加入未处理列表
+    #[arg(long, conflicts_with = "tui")]
+    pub unattended: bool,
+    /// 无人值守时同时处理的作品数
+    #[arg(long, default_value_t = 4)]
+    pub jobs: usize,
+
+    #[command(flatten)]
+    pub select: SelectArgs,
+}
+
+/// 作品的排序方式
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// 标题
+    Title,
+    /// 页数从少到多
+    Pagecount,
+    /// 最近阅读的在前
+    Lastreadtime,
+    /// 最近添加的在前
+    Newest,
+}
+
+/// 选择要处理哪些作品以及处理顺序
+#[derive(ClapArgs, Debug, Default, Clone)]
+pub struct SelectArgs {
+    /// 只处理标题匹配该正则的作品
+    #[arg(long)]
+    pub title: Option<String>,
+    /// 只处理这些扩展名的作品;例 zip,cbz
+    #[arg(long, value_delimiter = ',')]
+    pub extension: Vec<String>,
+    /// 最少页数
+    #[arg(long)]
+    pub min_pages: Option<i32>,
+    /// 最多页数
+    #[arg(long)]
+    pub max_pages: Option<i32>,
+    /// 只处理标记为新的作品
+    #[arg(long)]
+    pub new: bool,
+    /// 只处理该分类(id或名称)中的作品
+    #[arg(long)]
+    pub category: Option<String>,
+    /// 只处理文件中列出的arcid，每行一个
+    #[arg(long)]
+    pub arcid_file: Option<PathBuf>,
+    /// 排序方式，默认为LANraragi返回的顺序
+    #[arg(long, value_enum)]
+    pub sort: Option<SortKey>,
+    /// 反转排序
+    #[arg(long)]
+    pub reverse: bool,
+    /// 跳过前面的作品数
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// 最多处理的作品数
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// 列出每个作品被选中或跳过的原因
+    #[arg(long)]
+    pub explain: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 为无标签的作品搜索EH并添加标签(默认)
+    Run(RunArgs),
+    /// 重新处理未处理列表中的作品，同 `queue retry`
+    Retry(RunArgs),
+    /// 管理未处理列表
+    Queue {
+        #[command(subcommand)]
+        action: QueueAction,
+
+        #[command(flatten)]
+        options: RunArgs,
+    },
+    /// 把已知的画廊标签写入指定作品
+    Apply {
+        /// 作品的arcid
+        #[arg(long, requires = "url", required_unless_present_any = ["file", "approved"])]
+        arcid: Option<String>,
+
+        /// EH画廊的URL
+        #[arg(long, requires = "arcid")]
+        url: Option<String>,
+
+        /// 批量写入，每行为 arcid,url 的CSV文件，- 表示标准输入
+        #[arg(long, conflicts_with = "arcid")]
+        file: Option<PathBuf>,
+
+        /// 写入 review 中批准的全部选择
+        #[arg(long, conflicts_with_all = ["arcid", "file"])]
+        approved: bool,
+    },
+    /// 并发搜索所有无标签的作品，保存候选供 review 离线审核
+    Collect {
+        /// 同时进行的搜索数
+        #[arg(long, default_value_t = 4)]
+        jobs: usize,
+
+        #[command(flatten)]
+        select: SelectArgs,
+    },
+    /// 审核 collect 收集的候选，之后用 `apply --approved` 写入
+    Review {
+        /// 选择时显示候选作品标签的中文说明
+        #[arg(long)]
+        show_intro: bool,
+    },
+    /// 用中文词典翻译库中已有的英文标签，不搜索EH
+    Translate {
+        /// 只显示改动，不写入
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 把中文标签还原为EH的英文标签
+    Untranslate {
+        /// 只处理指定的作品，默认处理整个库
+        #[arg(long)]
+        arcid: Option<String>,
+
+        /// 只显示改动，不写入
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 标签数据库改名后，把库中的旧中文标签改为新名称
+    MigrateTags {
+        /// 对比的旧版本sha(前缀即可)，默认为上一个用过的版本
+        #[arg(long)]
+        from: Option<String>,
+
+        /// 只显示改动，不写入
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 批量维护库中的标签
+    Tags {
+        #[command(subcommand)]
+        action: TagsAction,
+    },
+    /// 撤销写入：指定批次时撤销整批，指定arcid时撤销该作品最近一次写入
+    Revert {
+        batch: Option<String>,
+
+        #[arg(long, conflicts_with = "batch")]
+        arcid: Option<String>,
+
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// 按标题卷号、作者/原作标签找出同一系列的作品，建议并写入LANraragi的合集
+    Tank {
+        /// 同时读取EH画廊页的父画廊和更新版本(每个有source标签的作品一次请求)
+        #[arg(long)]
+        parents: bool,
+
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// 显示对某个作品做过的所有操作
+    History { arcid: String },
+    /// 用过去的手动选择拟合评分权重和自动选择阈值
+    Calibrate {
+        /// 自动选择需要达到的精确率
+        #[arg(long, default_value_t = 0.99)]
+        precision: f64,
+    },
+    /// 库和本地状态的统计
+    Stats,
+    /// 检查配置、LANraragi、EH和标签数据库是否可用
+    Doctor,
+    /// 查看配置
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum QueueAction {
+    /// 列出未处理的作品
+    List {
+        /// 只列出该原因的作品
+        #[arg(long, value_enum)]
+        reason: Option<Reason>,
+    },
+    /// 重新处理未忽略的作品
+    Retry,
+    /// 永久忽略这些作品
+    Ignore {
+        #[arg(required = true)]
+        arcids: Vec<String>,
+    },
+    /// 清除条目，默认清除已忽略以外的全部
+    Clear {
+        /// 只清除该原因的条目
+        #[arg(long, value_enum)]
+        reason: Option<Reason>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// 显示合并后的配置，隐藏密钥
+    Show,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct EditOptions {
+    /// 只显示改动，不写入
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// 不再确认直接写入
     #[arg(short, long)]
-    pub cookies: String,
+    pub yes: bool,
 }
 
-pub fn args() -> &'static Args {
-    static ARGS: OnceLock<Args> = OnceLock::new();
-    ARGS.get_or_init(Args::parse)
+#[derive(Subcommand, Debug)]
+pub enum TagsAction {
+    /// 重命名标签
+    Rename {
+        from: String,
+        to: String,
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// 把多个同义标签合并为一个
+    Merge {
+        /// 合并后的标签
+        into: String,
+        /// 被合并的标签
+        #[arg(required = true)]
+        from: Vec<String>,
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// 删除指定namespace或匹配正则的标签
+    Delete {
+        /// 删除该namespace下的所有标签
+        #[arg(long, required_unless_present = "regex")]
+        namespace: Vec<String>,
+        /// 删除匹配该正则的标签
+        #[arg(long)]
+        regex: Option<String>,
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// 去除重复的标签(不区分大小写)
+    Dedupe {
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// 撤销某一批次的写入，不指定批次时列出所有批次
+    Undo {
+        batch: Option<String>,
+        #[command(flatten)]
+        options: EditOptions,
+    },
 }
 
 fn get_styles() -> clap::builder::Styles {
@@ -0,0 +1,261 @@
+use super::score::Scoring;
+use super::store::{labeled_examples, LabeledExample};
+
+use owo_colors::OwoColorize;
+use tabled::{builder::Builder, settings::Style};
+
+/// 某个阈值下自动选择的效果
+#[derive(Debug, Clone, Copy)]
+pub struct ThresholdStats {
+    pub threshold: f64,
+    /// 会被自动选择的样本数
+    pub selected: usize,
+    /// 其中选对的
+    pub correct: usize,
+    /// 有正确候选的样本总数
+    pub positives: usize,
+}
+
+impl ThresholdStats {
+    pub fn precision(&self) -> f64 {
+        if self.selected == 0 {
+            1.0
+        } else {
+            self.correct as f64 / self.selected as f64
+        }
+    }
+
+    pub fn recall(&self) -> f64 {
+        if self.positives == 0 {
+            0.0
+        } else {
+            self.correct as f64 / self.positives as f64
+        }
+    }
+}
+
+/// 每个样本的最高分以及最高分是否是用户选的那个
+fn best_scores(scoring: &Scoring, examples: &[LabeledExample]) -> Vec<(f64, bool)> {
+    examples
+        .iter()
+        .map(|example| {
+            let (index, best) = example
+                .features
+                .iter()
+                .map(|f| scoring.score(f))
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            (best, example.chosen == Some(index))
+        })
+        .collect()
+}
+
+pub fn threshold_stats(
+    scoring: &Scoring,
+    examples: &[LabeledExample],
+    threshold: f64,
+) -> ThresholdStats {
+    let scores = best_scores(scoring, examples);
+    let selected: Vec<_> = scores.iter().filter(|(s, _)| *s >= threshold).collect();
+    ThresholdStats {
+        threshold,
+        selected: selected.len(),
+        correct: selected.iter().filter(|(_, ok)| *ok).count(),
+        positives: examples.iter().filter(|e| e.chosen.is_some()).count(),
+    }
+}
+
+/// 选中的候选排在第一的比例，同分时看平均领先多少
+fn ranking_quality(scoring: &Scoring, examples: &[LabeledExample]) -> (usize, f64) {
+    let mut top = 0;
+    let mut margin = 0.0;
+    for example in examples {
+        let Some(chosen) = example.chosen else {
+            continue;
+        };
+        let scores: Vec<f64> = example.features.iter().map(|f| scoring.score(f)).collect();
+        let best_other = scores
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != chosen)
+            .map(|(_, s)| *s)
+            .fold(0.0, f64::max);
+        if scores[chosen] > best_other {
+            top += 1;
+        }
+        margin += scores[chosen] - best_other;
+    }
+    (top, margin)
+}
+
+/// 网格搜索相似度和页数的权重
+pub fn fit_weights(examples: &[LabeledExample]) -> Scoring {
+    (0..=20)
+        .map(|step| Scoring {
+            similarity_weight: step as f64 / 20.0,
+            page_weight: (20 - step) as f64 / 20.0,
+            auto: None,
+        })
+        .max_by(|a, b| {
+            let (a_top, a_margin) = ranking_quality(a, examples);
+            let (b_top, b_margin) = ranking_quality(b, examples);
+            a_top.cmp(&b_top).then(a_margin.total_cmp(&b_margin))
+        })
+        .unwrap()
+}
+
+/// 精确率不低于 `precision` 的最低阈值，召回率因此最高
+pub fn fit_threshold(
+    scoring: &Scoring,
+    examples: &[LabeledExample],
+    precision: f64,
+) -> Option<f64> {
+    let mut thresholds: Vec<f64> = best_scores(scoring, examples)
+        .into_iter()
+        .map(|(s, _)| s)
+        .collect();
+    thresholds.sort_by(f64::total_cmp);
+    thresholds.dedup();
+    // 向下取三位小数，方便写进配置；向上取会高过得出它的那个分数
+    thresholds
+        .into_iter()
+        .map(|t| (t * 1000.0).floor() / 1000.0)
+        .find(|t| {
+            let stats = threshold_stats(scoring, examples, *t);
+            stats.selected > 0 && stats.precision() >= precision
+        })
+}
+
+fn print_table(scoring: &Scoring, examples: &[LabeledExample], fitted: Option<f64>) {
+    let mut thresholds: Vec<f64> = (10..=20).map(|i| i as f64 / 20.0).collect();
+    if let Some(t) = fitted {
+        thresholds.push(t);
+        thresholds.sort_by(f64::total_cmp);
+        thresholds.dedup();
+    }
+    let mut builder = Builder::default();
+    builder.push_record(["阈值", "自动选择", "选对", "精确率", "召回率"]);
+    for threshold in thresholds {
+        let stats = threshold_stats(scoring, examples, threshold);
+        let mark = if fitted == Some(threshold) { "*" } else { "" };
+        builder.push_record([
+            format!("{:.3}{}", threshold, mark),
+            stats.selected.to_string(),
+            stats.correct.to_string(),
+            format!("{:.1}%", stats.precision() * 100.0),
+            format!("{:.1}%", stats.recall() * 100.0),
+        ]);
+    }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    println!("{}", table);
+}
+
+/// 用过去的手动选择拟合评分权重和自动选择阈值
+pub fn calibrate(current: &Scoring, precision: f64) -> Result<(), String> {
+    let examples = labeled_examples();
+    let positives = examples.iter().filter(|e| e.chosen.is_some()).count();
+    if positives == 0 {
+        return Err("还没有手动选择的记录，先用 run 处理一些作品".to_string());
+    }
+    println!(
+        "共 {} 条样本，其中 {} 条选中候选，{} 条跳过",
+        examples.len().bright_green(),
+        positives.bright_green(),
+        (examples.len() - positives).bright_yellow()
+    );
+
+    let (top, _) = ranking_quality(current, &examples);
+    println!(
+        "当前权重 相似度 {:.2} 页数 {:.2}: 选中项排第一 {}/{}",
+        current.similarity_weight,
+        current.page_weight,
+        top.cyan(),
+        positives
+    );
+    let mut fitted = fit_weights(&examples);
+    let (top, _) = ranking_quality(&fitted, &examples);
+    println!(
+        "拟合权重 相似度 {:.2} 页数 {:.2}: 选中项排第一 {}/{}",
+        fitted.similarity_weight,
+        fitted.page_weight,
+        top.bright_green(),
+        positives
+    );
+
+    fitted.auto = fit_threshold(&fitted, &examples, precision);
+    print_table(&fitted, &examples, fitted.auto);
+    match fitted.auto {
+        Some(threshold) => {
+            let stats = threshold_stats(&fitted, &examples, threshold);
+            println!(
+                "精确率 {:.1}% 的最低阈值为 {:.3}，可自动处理 {:.1}% 的作品",
+                precision * 100.0,
+                threshold.bright_green(),
+                stats.recall() * 100.0
+            );
+        }
+        None => println!(
+            "{}",
+            format!("没有阈值能达到 {:.1}% 的精确率", precision * 100.0).bright_yellow()
+        ),
+    }
+    println!();
+    println!("配置文件中使用:");
+    println!("[profiles.<name>.scoring]");
+    print!("{}", toml::to_string(&fitted).unwrap());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lanraragi::score::Features;
+
+    fn example(similarity: &[f64], chosen: Option<usize>) -> LabeledExample {
+        LabeledExample {
+            features: similarity
+                .iter()
+                .map(|&similarity| Features {
+                    similarity,
+                    page_match: false,
+                })
+                .collect(),
+            chosen,
+        }
+    }
+
+    #[test]
+    fn fit_threshold_lowest_with_precision() {
+        let scoring = Scoring {
+            similarity_weight: 1.0,
+            page_weight: 0.0,
+            auto: None,
+        };
+        let examples = [
+            example(&[0.875], Some(0)),
+            example(&[0.5], None),
+            // 最高分的不是用户选的
+            example(&[0.25, 0.75], Some(0)),
+        ];
+        assert_eq!(fit_threshold(&scoring, &examples, 1.0), Some(0.875));
+        assert_eq!(fit_threshold(&scoring, &examples, 0.5), Some(0.75));
+        assert_eq!(fit_threshold(&scoring, &examples, 0.0), Some(0.5));
+        assert_eq!(fit_threshold(&scoring, &examples[1..], 1.0), None);
+        assert_eq!(fit_threshold(&scoring, &[], 1.0), None);
+    }
+
+    #[test]
+    fn fit_threshold_keeps_its_own_sample() {
+        let scoring = Scoring {
+            similarity_weight: 1.0,
+            page_weight: 0.0,
+            auto: None,
+        };
+        let examples = [example(&[0.8755], Some(0)), example(&[0.5], None)];
+        let threshold = fit_threshold(&scoring, &examples, 1.0).unwrap();
+        assert_eq!(threshold, 0.875);
+        assert_eq!(threshold_stats(&scoring, &examples, threshold).selected, 1);
+    }
+}
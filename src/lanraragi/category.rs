@@ -0,0 +1,194 @@
+use super::archive::Archive;
+use super::config::Settings;
+use super::error::FetchError;
+use super::progress::log;
+use super::utils::fetch_raw_with_retry;
+
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use tokio::sync::Mutex;
+
+/// LANraragi 的分类
+#[derive(Debug, Deserialize)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub archives: Vec<String>,
+    /// 动态分类的搜索条件，静态分类为空
+    #[serde(default)]
+    pub search: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    data: Vec<SearchArchive>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchArchive {
+    arcid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Created {
+    category_id: String,
+}
+
+/// 分类名称到id的缓存，同时防止并发时重复创建
+fn category_ids() -> &'static Mutex<HashMap<String, String>> {
+    static IDS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    IDS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub async fn fetch_categories(settings: &Settings) -> Result<Vec<Category>, FetchError> {
+    let resp = fetch_raw_with_retry(|| {
+        reqwest::Client::new().get(format!("http://{}/api/categories", &settings.endpoint))
+    })
+    .await?;
+    Ok(resp.json::<Vec<Category>>().await?)
+}
+
+/// 取得分类(id或名称)中所有作品的arcid，动态分类通过搜索取得
+pub async fn category_arcids(
+    settings: &Settings,
+    key: &str,
+) -> Result<HashSet<String>, FetchError> {
+    let category = fetch_categories(settings)
+        .await?
+        .into_iter()
+        .find(|c| c.id == key || c.name == key)
+        .ok_or_else(|| FetchError::Other(format!("找不到分类 {}", key)))?;
+    if category.search.is_empty() {
+        return Ok(category.archives.into_iter().collect());
+    }
+    let resp = fetch_raw_with_retry(|| {
+        reqwest::Client::new()
+            .get(format!("http://{}/api/search", &settings.endpoint))
+            .query(&[("category", category.id.as_str()), ("start", "-1")])
+    })
+    .await?;
+    let result = resp.json::<SearchResult>().await?;
+    Ok(result.data.into_iter().map(|a| a.arcid).collect())
+}
+
+/// 按名称取得静态分类的id，不存在时 `create` 为 true 才创建，否则返回 None
+async fn category_id(
+    settings: &Settings,
+    name: &str,
+    create: bool,
+) -> Result<Option<String>, FetchError> {
+    let mut ids = category_ids().lock().await;
+    if let Some(id) = ids.get(name) {
+        return Ok(Some(id.clone()));
+    }
+    let existing = fetch_categories(settings)
+        .await?
+        .into_iter()
+        .find(|c| c.name == name);
+    let id = match existing {
+        Some(category) if !category.search.is_empty() => {
+            return Err(FetchError::Other(format!(
+                "分类 {} 是动态分类，无法添加作品",
+                name
+            )));
+        }
+        Some(category) => category.id,
+        None if !create => return Ok(None),
+        None => {
+            let resp = reqwest::Client::new()
+                .put(format!("http://{}/api/categories", &settings.endpoint))
+                .query(&[("name", name), ("key", &settings.api_key)])
+                .send()
+                .await?
+                .error_for_status()?;
+            let created = resp.json::<Created>().await?;
+            log(format!("已创建分类 {}", name.bright_green()));
+            created.category_id
+        }
+    };
+    ids.insert(name.to_string(), id.clone());
+    Ok(Some(id))
+}
+
+async fn add_to_category(settings: &Settings, name: &str, arcid: &str) -> Result<(), FetchError> {
+    let id = category_id(settings, name, true)
+        .await?
+        .expect("不存在时已经创建");
+    reqwest::Client::new()
+        .put(format!(
+            "http://{}/api/categories/{}/{}",
+            &settings.endpoint, id, arcid
+        ))
+        .query(&[("key", &settings.api_key)])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn remove_from_category(
+    settings: &Settings,
+    name: &str,
+    arcid: &str,
+) -> Result<(), FetchError> {
+    // 分类还不存在时作品也不可能在里面，不必为此创建
+    let Some(id) = category_id(settings, name, false).await? else {
+        return Ok(());
+    };
+    reqwest::Client::new()
+        .delete(format!(
+            "http://{}/api/categories/{}/{}",
+            &settings.endpoint, id, arcid
+        ))
+        .query(&[("key", &settings.api_key)])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn clear_isnew(settings: &Settings, arcid: &str) -> Result<(), FetchError> {
+    reqwest::Client::new()
+        .delete(format!(
+            "http://{}/api/archives/{}/isnew",
+            &settings.endpoint, arcid
+        ))
+        .query(&[("key", &settings.api_key)])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn warn(archive: &Archive, action: &str, result: Result<(), FetchError>) {
+    if let Err(err) = result {
+        log(format!("⚠️{} {}: {}", action, archive.title, err.yellow()));
+    }
+}
+
+/// 写入标签后：加入已标记分类、移出未处理分类并清除新作品标记，失败只警告
+pub async fn mark_tagged(settings: &Settings, archive: &Archive) {
+    if let Some(name) = &settings.tagged_category {
+        let result = add_to_category(settings, name, &archive.arcid).await;
+        warn(archive, "加入分类失败", result);
+    }
+    if let Some(name) = &settings.unhandled_category {
+        let result = remove_from_category(settings, name, &archive.arcid).await;
+        warn(archive, "移出分类失败", result);
+    }
+    if settings.clear_new && archive.isnew == "true" {
+        let result = clear_isnew(settings, &archive.arcid).await;
+        warn(archive, "清除新作品标记失败", result);
+    }
+}
+
+/// 加入未处理列表后：加入未处理分类，失败只警告
+pub async fn mark_unhandled(settings: &Settings, archive: &Archive) {
+    if let Some(name) = &settings.unhandled_category {
+        let result = add_to_category(settings, name, &archive.arcid).await;
+        warn(archive, "加入分类失败", result);
+    }
+}
@@ -0,0 +1,245 @@
+use super::archive::Archive;
+use super::journal::{batch_id, resume_batch_id};
+use super::store::store;
+use super::unhandle::Reason;
+use super::untranslated::save_untranslated;
+
+use chrono::Utc;
+use owo_colors::OwoColorize;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// 单个作品的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Tagged,
+    Unhandled(Reason),
+}
+
+/// 可以用 `--resume` 继续的运行状态
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RunState {
+    pub run_id: String,
+    pub started_at: i64,
+    /// 当前处理到的位置
+    pub position: usize,
+    pub total: usize,
+    pub outcomes: BTreeMap<String, Outcome>,
+    pub finished: bool,
+}
+
+impl RunState {
+    fn new() -> Self {
+        Self {
+            run_id: batch_id().to_string(),
+            started_at: Utc::now().timestamp(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_processed(&self, arcid: &str) -> bool {
+        self.outcomes.contains_key(arcid)
+    }
+
+    pub fn print_summary(&self) {
+        let tagged = self
+            .outcomes
+            .values()
+            .filter(|o| **o == Outcome::Tagged)
+            .count();
+        let mut unhandled: BTreeMap<String, usize> = BTreeMap::new();
+        for outcome in self.outcomes.values() {
+            if let Outcome::Unhandled(reason) = outcome {
+                *unhandled.entry(format!("{:?}", reason)).or_default() += 1;
+            }
+        }
+        println!(
+            "运行 {}: 已标记 {}，未处理 {}，剩余 {}",
+            self.run_id.cyan(),
+            tagged.bright_green(),
+            (self.outcomes.len() - tagged).bright_yellow(),
+            self.total.saturating_sub(self.position).cyan()
+        );
+        for (reason, count) in unhandled {
+            println!("  {:<14} {}", reason, count);
+        }
+    }
+}
+
+pub fn run_state() -> &'static Mutex<RunState> {
+    static RUN_STATE: OnceLock<Mutex<RunState>> = OnceLock::new();
+    RUN_STATE.get_or_init(|| Mutex::new(RunState::default()))
+}
+
+/// 写入 LANraragi 和记录结果期间持有，中断时等待它释放后再退出
+pub fn write_lock() -> &'static tokio::sync::Mutex<()> {
+    static WRITE_LOCK: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+    WRITE_LOCK.get_or_init(|| tokio::sync::Mutex::new(()))
+}
+
+// 保存运行进度和 `arcids` 的处理结果
+fn save(conn: &mut Connection, state: &RunState, arcids: &[&String]) {
+    let tx = conn.transaction().unwrap();
+    tx.execute(
+        "INSERT INTO runs (run_id, started_at, position, total, finished) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (run_id) DO UPDATE SET
+             position = excluded.position, total = excluded.total, finished = excluded.finished",
+        params![
+            state.run_id,
+            state.started_at,
+            state.position,
+            state.total,
+            state.finished
+        ],
+    )
+    .unwrap();
+    for arcid in arcids {
+        tx.execute(
+            "INSERT OR REPLACE INTO run_outcomes (run_id, arcid, outcome) VALUES (?1, ?2, ?3)",
+            params![
+                state.run_id,
+                arcid,
+                serde_json::to_string(&state.outcomes[*arcid]).unwrap()
+            ],
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+}
+
+/// 最近一次运行
+fn load() -> Option<RunState> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut state = conn
+        .query_row(
+            "SELECT run_id, started_at, position, total, finished FROM runs
+             ORDER BY started_at DESC, rowid DESC LIMIT 1",
+            [],
+            |row| {
+                Ok(RunState {
+                    run_id: row.get(0)?,
+                    started_at: row.get(1)?,
+                    position: row.get(2)?,
+                    total: row.get(3)?,
+                    outcomes: BTreeMap::new(),
+                    finished: row.get(4)?,
+                })
+            },
+        )
+        .optional()
+        .unwrap()?;
+    let mut stmt = conn
+        .prepare("SELECT arcid, outcome FROM run_outcomes WHERE run_id = ?1")
+        .unwrap();
+    let rows = stmt
+        .query_map(params![state.run_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .unwrap();
+    for (arcid, outcome) in rows.map(Result::unwrap) {
+        if let Ok(outcome) = serde_json::from_str(&outcome) {
+            state.outcomes.insert(arcid, outcome);
+        }
+    }
+    Some(state)
+}
+
+pub fn save_run_state() {
+    let state = run_state().lock().unwrap_or_else(PoisonError::into_inner);
+    let arcids: Vec<&String> = state.outcomes.keys().collect();
+    save(
+        &mut store().lock().unwrap_or_else(PoisonError::into_inner),
+        &state,
+        &arcids,
+    );
+}
+
+/// 开始新的运行，`resume` 时继续上次未完成的运行，返回还没有处理的作品
+pub fn start_run(resume: bool, archives: &[Archive]) -> Vec<Archive> {
+    let previous = if resume { load() } else { None };
+    let mut state = match previous {
+        Some(state) if !state.finished => {
+            println!(
+                "继续运行 {}，已处理 {} 条",
+                state.run_id.cyan(),
+                state.outcomes.len().bright_green()
+            );
+            resume_batch_id(&state.run_id);
+            state
+        }
+        Some(_) => {
+            println!("上次的运行已经完成，开始新的运行");
+            RunState::new()
+        }
+        None => RunState::new(),
+    };
+    let remaining: Vec<Archive> = archives
+        .iter()
+        .filter(|archive| !state.is_processed(&archive.arcid))
+        .cloned()
+        .collect();
+    state.position = state.outcomes.len();
+    state.total = state.position + remaining.len();
+    *run_state().lock().unwrap_or_else(PoisonError::into_inner) = state;
+    save_run_state();
+    remaining
+}
+
+/// 没有进行中的运行时(如 apply)不记录
+pub fn record_outcome(arcid: &str, outcome: Outcome) {
+    let mut state = run_state().lock().unwrap_or_else(PoisonError::into_inner);
+    if state.run_id.is_empty() {
+        return;
+    }
+    state.outcomes.insert(arcid.to_string(), outcome);
+    state.position = state.outcomes.len();
+    let arcid = arcid.to_string();
+    save(
+        &mut store().lock().unwrap_or_else(PoisonError::into_inner),
+        &state,
+        &[&arcid],
+    );
+}
+
+pub fn finish_run() {
+    {
+        let mut state = run_state().lock().unwrap_or_else(PoisonError::into_inner);
+        state.finished = true;
+        state.position = state.total;
+    }
+    save_run_state();
+    run_state()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .print_summary();
+}
+
+/// 中途退出，保留状态以便继续
+pub fn pause_run() {
+    save_run_state();
+    run_state()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .print_summary();
+    println!("使用 --resume 继续");
+}
+
+/// Ctrl-C 时等待正在进行的写入完成，保存状态并打印汇总后退出
+pub fn handle_interrupt() -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        let _guard = write_lock().lock().await;
+        println!();
+        println!("{}", "收到中断，保存运行状态...".bright_yellow());
+        pause_run();
+        if let Err(err) = save_untranslated() {
+            println!("{}", format!("导出未翻译标签失败: {}", err).red());
+        }
+        std::process::exit(130);
+    })
+}
@@ -0,0 +1,226 @@
+use super::archive::Archive;
+use super::args::SelectArgs;
+use super::commands::{load_dictionary, search_candidates, skip_archive};
+use super::config::Settings;
+use super::eh::Choice;
+use super::hash::{or_hash_search, HASH_QUERY};
+use super::progress::multi_progress;
+use super::select::{select_archives, untagged_archives};
+use super::store::{
+    collected_arcids, pending_reviews, record_archive, record_decision, save_review,
+    set_review_status, Decision, Review, ReviewStatus,
+};
+use super::unhandle::Reason;
+
+use futures_util::{stream, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use owo_colors::OwoColorize;
+use std::collections::BTreeMap;
+
+/// 收集阶段单个作品的结果
+enum Collected {
+    Pending,
+    Auto,
+    Unhandled(Reason),
+}
+
+async fn collect_one(settings: &Settings, archive: Archive) -> Collected {
+    record_archive(&archive);
+    let query = archive.regex_title();
+    let result = if query.is_empty() {
+        None
+    } else {
+        Some(search_candidates(settings, &archive, &query).await)
+    };
+    let candidates = match or_hash_search(settings, &archive, result).await {
+        None => {
+            skip_archive(settings, &archive, Reason::NoTitleMatch, vec![], None).await;
+            return Collected::Unhandled(Reason::NoTitleMatch);
+        }
+        Some(Ok(candidates)) => candidates,
+        Some(Err(err)) => {
+            let message = Some(err.to_string());
+            skip_archive(settings, &archive, Reason::Error, vec![query], message).await;
+            return Collected::Unhandled(Reason::Error);
+        }
+    };
+    if candidates.gls.is_empty() {
+        skip_archive(settings, &archive, Reason::NoResults, vec![query], None).await;
+        return Collected::Unhandled(Reason::NoResults);
+    }
+    let auto = candidates.auto_select(&settings.scoring);
+    let search_id = candidates.search_id;
+    let review = Review {
+        archive,
+        // 标题无法提取关键词时结果来自页面哈希
+        query: if query.is_empty() {
+            HASH_QUERY.to_string()
+        } else {
+            query
+        },
+        search_id: Some(search_id),
+        gls: candidates.gls,
+    };
+    match auto {
+        Some(i) => {
+            let url = review.gls[i].url();
+            record_decision(
+                &review.archive.arcid,
+                Some(search_id),
+                Decision::Auto,
+                Some(url),
+            );
+            save_review(&review, ReviewStatus::Approved, Some(url));
+            Collected::Auto
+        }
+        None => {
+            save_review(&review, ReviewStatus::Pending, None);
+            Collected::Pending
+        }
+    }
+}
+
+/// 并发搜索所有无标签的作品，保存候选等待审核
+pub async fn collect(settings: &Settings, jobs: usize, select: &SelectArgs) -> Result<(), String> {
+    settings.require(true)?;
+    let archives = Archive::fetch_archives(settings).await;
+    let collected = collected_arcids();
+    let targets: Vec<Archive> = archives
+        .into_iter()
+        .filter(|archive| !collected.contains(&archive.arcid))
+        .collect();
+    let targets = untagged_archives(settings, targets, select.explain);
+    let targets = select_archives(settings, targets, select).await?;
+    println!(
+        "{} 条无标签作品需要搜索，已收集的 {} 条跳过",
+        targets.len().bright_green(),
+        collected.len().cyan()
+    );
+
+    let bar = multi_progress().add(ProgressBar::new(targets.len() as u64));
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
+            )
+            .map_err(|e| e.to_string())?
+            .progress_chars("#>-"),
+    );
+    let mut pending = 0;
+    let mut auto = 0;
+    let mut unhandled: BTreeMap<String, usize> = BTreeMap::new();
+    let mut results = stream::iter(targets)
+        .map(|archive| collect_one(settings, archive))
+        .buffer_unordered(jobs.max(1));
+    while let Some(result) = results.next().await {
+        match result {
+            Collected::Pending => pending += 1,
+            Collected::Auto => auto += 1,
+            Collected::Unhandled(reason) => {
+                *unhandled.entry(format!("{:?}", reason)).or_default() += 1
+            }
+        }
+        bar.inc(1);
+    }
+    bar.finish();
+
+    println!(
+        "待审核 {}，自动批准 {}",
+        pending.bright_green(),
+        auto.bright_green()
+    );
+    for (reason, count) in unhandled {
+        println!("  {:<14} {}", reason, count);
+    }
+    println!("使用 review 审核，之后用 apply --approved 写入");
+    Ok(())
+}
+
+/// 用收集到的候选逐个审核，只记录选择，不写入
+pub async fn review(settings: &Settings, show_intro: bool) -> Result<(), String> {
+    let reviews = pending_reviews();
+    if reviews.is_empty() {
+        println!("没有待审核的作品，先运行 collect");
+        return Ok(());
+    }
+    let dict = if show_intro {
+        Some(load_dictionary(settings).await?.0)
+    } else {
+        None
+    };
+    let total = reviews.len();
+    let mut approved = 0;
+    let mut skipped = 0;
+    for (i, mut review) in reviews.into_iter().enumerate() {
+        println!("[{}/{}]", (i + 1).cyan(), total);
+        let arcid = review.archive.arcid.clone();
+        loop {
+            match review.archive.print_and_choose(
+                &review.gls,
+                None,
+                &settings.scoring,
+                dict.as_ref(),
+            ) {
+                Choice::Select(index) => {
+                    let url = review.gls[index].url();
+                    record_decision(&arcid, review.search_id, Decision::Selected, Some(url));
+                    set_review_status(&arcid, ReviewStatus::Approved, Some(url));
+                    approved += 1;
+                }
+                Choice::Url(gl) => {
+                    record_decision(&arcid, None, Decision::Manual, Some(gl.url()));
+                    set_review_status(&arcid, ReviewStatus::Approved, Some(gl.url()));
+                    approved += 1;
+                }
+                Choice::Search(query) => {
+                    // 只有重新搜索需要联网
+                    settings.require(true)?;
+                    match search_candidates(settings, &review.archive, &query).await {
+                        Ok(candidates) => {
+                            review.query = query;
+                            review.search_id = Some(candidates.search_id);
+                            review.gls = candidates.gls;
+                            save_review(&review, ReviewStatus::Pending, None);
+                        }
+                        Err(err) => println!("❌search failed: {}", err.red()),
+                    }
+                    continue;
+                }
+                Choice::Skip => {
+                    if review.search_id.is_some() {
+                        record_decision(&arcid, review.search_id, Decision::Skipped, None);
+                    }
+                    set_review_status(&arcid, ReviewStatus::Skipped, None);
+                    skip_archive(
+                        settings,
+                        &review.archive,
+                        Reason::UserSkipped,
+                        vec![review.query.clone()],
+                        None,
+                    )
+                    .await;
+                    skipped += 1;
+                }
+                Choice::Quit => {
+                    print_review_summary(approved, skipped, total - approved - skipped);
+                    return Ok(());
+                }
+            }
+            break;
+        }
+    }
+    print_review_summary(approved, skipped, 0);
+    Ok(())
+}
+
+fn print_review_summary(approved: usize, skipped: usize, remaining: usize) {
+    println!(
+        "已批准 {}，跳过 {}，剩余 {}",
+        approved.bright_green(),
+        skipped.bright_yellow(),
+        remaining.cyan()
+    );
+    if approved > 0 {
+        println!("使用 apply --approved 写入");
+    }
+}
@@ -0,0 +1,854 @@
+use super::archive::Archive;
+use super::args::{EditOptions, QueueAction, RunArgs};
+use super::category::{mark_tagged, mark_unhandled};
+use super::checkpoint::{
+    finish_run, handle_interrupt, pause_run, record_outcome, start_run, write_lock, Outcome,
+};
+use super::config::Settings;
+use super::eh::{evict_galleries, fetch_eh, Choice, GL};
+use super::error::FetchError;
+use super::hash::{or_hash_search, HashMatches, HASH_QUERY};
+use super::journal::read_journal;
+use super::maintain::{apply, print_batches, revert_archive, undo_changes};
+use super::migrate::save_snapshot;
+use super::overrides::TagOverrides;
+use super::plugin::plugin_fallback;
+use super::score::{Features, Scoring};
+use super::select::{explain, select_archives, untagged_archives};
+use super::store::{
+    history, record_archive, record_candidates, record_decision, record_search, Decision,
+    HistoryEvent,
+};
+use super::tag::{fetch_latest_cn_tag, CnTags, TagDictionary};
+use super::tui::review;
+use super::unattended::process_unattended;
+use super::unhandle::{
+    add_and_save_no_handle, all_entries, clear_no_handle, ignore_no_handle, queued_entries,
+    remove_no_handle, Reason,
+};
+use super::untranslated::save_untranslated;
+
+use chrono::{DateTime, Local};
+use owo_colors::OwoColorize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use tokio::task::JoinHandle;
+
+/// 下载标签数据库，保存快照并合并覆盖词典
+pub async fn load_dictionary(settings: &Settings) -> Result<(TagDictionary, CnTags), String> {
+    let tags = fetch_latest_cn_tag(settings.db_format)
+        .await
+        .map_err(|e| format!("fetch latest cn tag failed: {}", e))?;
+    let dict = TagDictionary::parse(&tags, settings.db_format)
+        .map_err(|e| format!("获取标签失败: {}", e))?;
+    println!(
+        "标签数据库版本: {} ({})",
+        dict.head.version.bright_green(),
+        dict.head.sha.chars().take(7).collect::<String>().cyan()
+    );
+    save_snapshot(&dict);
+    let mut tag_cn = CnTags::new(dict.to_cn_map());
+    if let Some(path) = &settings.overrides {
+        let overrides = TagOverrides::load(path).map_err(|e| format!("读取覆盖词典失败: {}", e))?;
+        overrides.apply(&mut tag_cn, &dict.namespaces);
+    }
+    Ok((dict, tag_cn))
+}
+
+/// 同时获取作品列表和标签数据库
+pub async fn load_library(
+    settings: &Settings,
+) -> Result<(Vec<Archive>, TagDictionary, CnTags), String> {
+    println!("获取Lanraragi作品和最新的cn标签...");
+    let (all_archive, dict) =
+        tokio::join!(Archive::fetch_archives(settings), load_dictionary(settings));
+    let (dict, tag_cn) = dict?;
+    Ok((all_archive, dict, tag_cn))
+}
+
+pub fn print_effective_dict(cn_tags: &CnTags) {
+    let sorted: BTreeMap<_, _> = cn_tags.tags.iter().collect();
+    println!("{}", serde_json::to_string_pretty(&sorted).unwrap());
+}
+
+/// 加入未处理列表并记录到运行状态
+pub async fn skip_archive(
+    settings: &Settings,
+    archive: &Archive,
+    reason: Reason,
+    queries: Vec<String>,
+    message: Option<String>,
+) {
+    {
+        let _guard = write_lock().lock().await;
+        add_and_save_no_handle(archive.clone(), reason, queries, message);
+        record_outcome(&archive.arcid, Outcome::Unhandled(reason));
+    }
+    mark_unhandled(settings, archive).await;
+}
+
+/// 搜索失败时先尝试 LANraragi 插件，仍然没有标签才加入未处理列表；插件写入成功时返回 true
+pub async fn fallback_or_skip(
+    settings: &Settings,
+    archive: &Archive,
+    tag_cn: &CnTags,
+    reason: Reason,
+    queries: Vec<String>,
+    message: Option<String>,
+) -> bool {
+    if plugin_fallback(settings, archive, tag_cn).await.is_some() {
+        return true;
+    }
+    skip_archive(settings, archive, reason, queries, message).await;
+    false
+}
+
+/// 一次搜索的结果
+pub struct Candidates {
+    pub search_id: i64,
+    pub gls: Vec<GL>,
+    pub features: Vec<Features>,
+    /// 页面哈希搜索的命中页数，标题搜索时为 None
+    pub hash: Option<HashMatches>,
+}
+
+impl Candidates {
+    /// 开启自动选择时可以直接采用的候选：哈希搜索只采用唯一命中多页的画廊，
+    /// 标题搜索按评分阈值
+    pub fn auto_select(&self, scoring: &Scoring) -> Option<usize> {
+        scoring.auto?;
+        match &self.hash {
+            Some(hash) => hash.confident(),
+            None => scoring.auto_select(&self.features),
+        }
+    }
+
+    /// 每个候选命中的页数
+    pub fn matches(&self) -> Option<&[usize]> {
+        self.hash.as_ref().map(|hash| hash.matches.as_slice())
+    }
+}
+
+/// 搜索并记录搜索结果和候选评分
+pub async fn search_candidates(
+    settings: &Settings,
+    archive: &Archive,
+    query: &str,
+) -> Result<Candidates, FetchError> {
+    let gls = match archive.search_from_eh(settings, query).await {
+        Ok(gls) => gls,
+        Err(err) => {
+            record_search(&archive.arcid, query, 0, Some(&err.to_string()));
+            return Err(err);
+        }
+    };
+    let search_id = record_search(&archive.arcid, query, gls.len(), None);
+    let features: Vec<_> = gls.iter().map(|gl| archive.features(gl)).collect();
+    let candidates: Vec<_> = gls
+        .iter()
+        .zip(&features)
+        .map(|(gl, f)| (gl.url(), gl.title(), *f))
+        .collect();
+    record_candidates(search_id, &candidates, &settings.scoring);
+    Ok(Candidates {
+        search_id,
+        gls,
+        features,
+        hash: None,
+    })
+}
+
+/// 把选中画廊的标签写入作品
+pub async fn accept_candidate(
+    settings: &Settings,
+    archive: &Archive,
+    gl: &GL,
+    tag_cn: &CnTags,
+    search_id: Option<i64>,
+    decision: Decision,
+) -> Result<(), FetchError> {
+    record_decision(&archive.arcid, search_id, decision, Some(gl.url()));
+    // 写入期间收到中断要等写完再退出
+    let _guard = write_lock().lock().await;
+    let tags = gl.get_tags_from_eh_gl(settings, tag_cn).await?;
+    write_tags(settings, archive, &tags).await
+}
+
+/// 把翻译好的标签追加到作品并记录结果，调用方需持有 `write_lock`
+pub async fn write_tags(
+    settings: &Settings,
+    archive: &Archive,
+    tags: &str,
+) -> Result<(), FetchError> {
+    archive
+        .change_tags_to_lanraragi(settings, &format!("{},{}", &archive.tags, tags))
+        .await?;
+    remove_no_handle(&archive.arcid);
+    record_outcome(&archive.arcid, Outcome::Tagged);
+    mark_tagged(settings, archive).await;
+    Ok(())
+}
+
+/// 单个作品的询问结果
+enum Prompted {
+    Tagged,
+    Skipped,
+    Quit,
+}
+
+impl Prompted {
+    fn fallback(tagged: bool) -> Self {
+        if tagged {
+            Prompted::Tagged
+        } else {
+            Prompted::Skipped
+        }
+    }
+}
+
+/// 询问一个作品，`result` 为预取的搜索结果，用户重新搜索时循环
+async fn prompt_archive(
+    settings: &Settings,
+    archive: &Archive,
+    tag_cn: &CnTags,
+    intro: Option<&TagDictionary>,
+    title: String,
+    mut result: Result<Candidates, FetchError>,
+) -> Prompted {
+    let mut queries = vec![title];
+    // 用户手动搜索后，没有结果也继续询问
+    let mut manual = false;
+    loop {
+        let (search_id, gls, auto, matches) = match result {
+            Ok(candidates) if !candidates.gls.is_empty() => {
+                let auto = if manual {
+                    None
+                } else {
+                    candidates.auto_select(&settings.scoring)
+                };
+                let matches = candidates.hash.map(|hash| hash.matches);
+                (Some(candidates.search_id), candidates.gls, auto, matches)
+            }
+            Ok(candidates) if manual => {
+                println!("❌no result for: {}", queries.last().unwrap().red());
+                (Some(candidates.search_id), vec![], None, None)
+            }
+            Ok(_) => {
+                println!("❌no result for: {}", &archive.title.red());
+                let tagged =
+                    fallback_or_skip(settings, archive, tag_cn, Reason::NoResults, queries, None)
+                        .await;
+                return Prompted::fallback(tagged);
+            }
+            Err(err) => {
+                println!("❌search failed: {}", err.red());
+                if !manual {
+                    let message = Some(err.to_string());
+                    let tagged = fallback_or_skip(
+                        settings,
+                        archive,
+                        tag_cn,
+                        Reason::Error,
+                        queries,
+                        message,
+                    )
+                    .await;
+                    return Prompted::fallback(tagged);
+                }
+                (None, vec![], None, None)
+            }
+        };
+        let choice = match auto {
+            Some(i) => {
+                println!(
+                    "{} 自动选择 {}",
+                    &archive.title.bright_blue(),
+                    (i + 1).cyan()
+                );
+                Choice::Select(i)
+            }
+            None => archive.print_and_choose(&gls, matches.as_deref(), &settings.scoring, intro),
+        };
+        let result = match choice {
+            Choice::Select(i) => {
+                let decision = if auto.is_some() {
+                    Decision::Auto
+                } else {
+                    Decision::Selected
+                };
+                accept_candidate(settings, archive, &gls[i], tag_cn, search_id, decision).await
+            }
+            Choice::Url(gl) => {
+                accept_candidate(settings, archive, &gl, tag_cn, None, Decision::Manual).await
+            }
+            Choice::Search(query) => {
+                result = search_candidates(settings, archive, &query).await;
+                queries.push(query);
+                manual = true;
+                continue;
+            }
+            Choice::Skip => {
+                if search_id.is_some() {
+                    record_decision(&archive.arcid, search_id, Decision::Skipped, None);
+                }
+                skip_archive(settings, archive, Reason::UserSkipped, queries, None).await;
+                println!("❌not handle");
+                return Prompted::Skipped;
+            }
+            Choice::Quit => return Prompted::Quit,
+        };
+        return match result {
+            Ok(()) => Prompted::Tagged,
+            Err(err) => {
+                println!("❌write failed: {}", err.red());
+                skip_archive(
+                    settings,
+                    archive,
+                    Reason::Error,
+                    queries,
+                    Some(err.to_string()),
+                )
+                .await;
+                Prompted::Skipped
+            }
+        };
+    }
+}
+
+/// 每次搜索后预取画廊页的候选数
+static PREFETCH_GALLERIES: usize = 2;
+
+/// 预取评分最高的几个候选的画廊页
+pub async fn prefetch_top(settings: &Settings, candidates: &Candidates) {
+    let mut ranked: Vec<(usize, f64)> = candidates
+        .features
+        .iter()
+        .map(|f| settings.scoring.score(f))
+        .enumerate()
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (index, _) in ranked.into_iter().take(PREFETCH_GALLERIES) {
+        candidates.gls[index].prefetch_gallery(settings).await;
+    }
+}
+
+/// 后台搜索的结果，标题无法提取关键词时没有搜索
+struct Prefetched {
+    archive: Archive,
+    title: String,
+    result: Option<Result<Candidates, FetchError>>,
+}
+
+fn spawn_prefetch(settings: &Settings, archive: Archive) -> JoinHandle<Prefetched> {
+    let settings = settings.clone();
+    tokio::spawn(async move {
+        record_archive(&archive);
+        let mut title = archive.regex_title();
+        let result = if title.is_empty() {
+            None
+        } else {
+            Some(search_candidates(&settings, &archive, &title).await)
+        };
+        let result = or_hash_search(&settings, &archive, result).await;
+        if let Some(Ok(candidates)) = &result {
+            prefetch_top(&settings, candidates).await;
+            // 标题无法提取关键词时结果来自页面哈希
+            if title.is_empty() {
+                title = HASH_QUERY.to_string();
+            }
+        }
+        Prefetched {
+            archive,
+            title,
+            result,
+        }
+    })
+}
+
+/// 逐个在终端中询问，后面 `options.prefetch` 个作品在后台提前搜索；
+/// 返回成功写入的数量以及是否全部处理完
+async fn prompt_archives(
+    settings: &Settings,
+    archives: &[Archive],
+    dict: &TagDictionary,
+    tag_cn: &CnTags,
+    options: &RunArgs,
+) -> (usize, bool) {
+    let interrupt = handle_interrupt();
+    let mut run_count = 0;
+    let mut handled = 0;
+    let mut finished = true;
+    let intro = options.show_intro.then_some(dict);
+
+    let mut upcoming = archives.iter();
+    let mut window: VecDeque<JoinHandle<Prefetched>> = VecDeque::new();
+    loop {
+        while window.len() <= options.prefetch {
+            let Some(archive) = upcoming.next() else {
+                break;
+            };
+            window.push_back(spawn_prefetch(settings, archive.clone()));
+        }
+        let Some(next) = window.pop_front() else {
+            break;
+        };
+        let Prefetched {
+            archive,
+            title,
+            result,
+        } = next.await.unwrap();
+        run_count += 1;
+        // 后台搜索不输出，轮到该作品时再显示
+        if let Some(Ok(Candidates { hash: Some(_), .. })) = &result {
+            println!("按页面哈希搜索: {}", archive.title.bright_yellow());
+        } else if !title.is_empty() {
+            println!("match group: {}", title.bright_yellow());
+        }
+        let prefetched: Vec<String> = match &result {
+            Some(Ok(candidates)) => candidates
+                .gls
+                .iter()
+                .map(|gl| gl.url().to_string())
+                .collect(),
+            _ => vec![],
+        };
+        let prompted = match result {
+            Some(result) => prompt_archive(settings, &archive, tag_cn, intro, title, result).await,
+            None => {
+                println!("❌title no match: {}", &archive.title.red());
+                let reason = Reason::NoTitleMatch;
+                Prompted::fallback(
+                    fallback_or_skip(settings, &archive, tag_cn, reason, vec![], None).await,
+                )
+            }
+        };
+        evict_galleries(prefetched.iter().map(String::as_str));
+        match prompted {
+            Prompted::Tagged => {
+                handled += 1;
+                println!(
+                    "已处理 {}/{}  {:.1}%",
+                    run_count.green(),
+                    archives.len().cyan(),
+                    (run_count as f32 / archives.len() as f32 * 100.0).bright_green()
+                );
+            }
+            Prompted::Skipped => {}
+            Prompted::Quit => {
+                finished = false;
+                break;
+            }
+        }
+    }
+    for pending in window {
+        pending.abort();
+    }
+    interrupt.abort();
+    (handled, finished)
+}
+
+/// 处理作品列表，返回成功写入的数量
+pub async fn process_archives(
+    settings: &Settings,
+    archives: &[Archive],
+    dict: &TagDictionary,
+    tag_cn: &CnTags,
+    options: &RunArgs,
+) -> usize {
+    let archives = start_run(options.resume, archives);
+    let (handled, finished) = if options.unattended {
+        (
+            process_unattended(settings, archives, tag_cn, options.jobs).await,
+            true,
+        )
+    } else if options.tui {
+        let (handled, result) = review(settings, archives, dict, tag_cn, options.prefetch).await;
+        // 终端出错时和中途退出一样保留运行状态
+        let finished = result.unwrap_or_else(|err| {
+            println!("{}", format!("终端出错: {}", err).red());
+            false
+        });
+        (handled, finished)
+    } else {
+        prompt_archives(settings, &archives, dict, tag_cn, options).await
+    };
+    if finished {
+        finish_run();
+    } else {
+        pause_run();
+    }
+    export_untranslated();
+    handled
+}
+
+/// 写出本次运行收集到的未翻译标签
+pub fn export_untranslated() {
+    match save_untranslated() {
+        Ok(0) => {}
+        Ok(count) => println!(
+            "有 {} 个标签没有中文翻译，已导出到 untranslated.csv 和 untranslated.md",
+            count.bright_yellow()
+        ),
+        Err(err) => println!("{}", format!("导出未翻译标签失败: {}", err).red()),
+    }
+}
+
+fn check_run_options(settings: &Settings, options: &RunArgs) -> Result<(), String> {
+    if options.unattended && settings.scoring.auto.is_none() {
+        return Err(
+            "--unattended 需要设置自动选择阈值(--auto 或配置文件 scoring 中的 auto)".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// 为无标签的作品添加标签
+pub async fn run(settings: &Settings, options: &RunArgs) -> Result<(), String> {
+    settings.require(true)?;
+    check_run_options(settings, options)?;
+    let (all_archive, dict, tag_cn) = load_library(settings).await?;
+    let total = all_archive.len();
+    let targets = untagged_archives(settings, all_archive, options.select.explain);
+    println!(
+        "共有 {} 条作品，其中 {} 条无标签",
+        total.bright_green(),
+        targets.len().bright_green()
+    );
+    let targets = select_archives(settings, targets, &options.select).await?;
+    process_archives(settings, &targets, &dict, &tag_cn, options).await;
+    println!("结束");
+    Ok(())
+}
+
+/// 重新处理未处理列表中仍然没有标签的作品，已忽略的除外
+pub async fn retry(settings: &Settings, options: &RunArgs) -> Result<(), String> {
+    settings.require(true)?;
+    check_run_options(settings, options)?;
+    let (all_archive, dict, tag_cn) = load_library(settings).await?;
+    let queued = queued_entries();
+    let targets: Vec<Archive> = all_archive
+        .into_iter()
+        .filter(|archive| queued.iter().any(|q| q.archive.arcid == archive.arcid))
+        .filter(|archive| {
+            let (untagged, reason) = settings.untagged.judge(archive);
+            if options.select.explain {
+                explain(archive, untagged, &reason);
+            }
+            // 已经在别处打过标签的直接移出列表
+            if !untagged {
+                remove_no_handle(&archive.arcid);
+            }
+            untagged
+        })
+        .collect();
+    println!(
+        "未处理列表中有 {} 条作品，其中 {} 条仍无标签",
+        queued.len().bright_green(),
+        targets.len().bright_green()
+    );
+    let targets = select_archives(settings, targets, &options.select).await?;
+    process_archives(settings, &targets, &dict, &tag_cn, options).await;
+    println!("结束");
+    Ok(())
+}
+
+fn print_queue(reason: Option<Reason>) {
+    let queue = all_entries();
+    let mut count = 0;
+    for entry in queue
+        .iter()
+        .filter(|entry| reason.is_none_or(|r| entry.reason == r))
+    {
+        count += 1;
+        let time = DateTime::from_timestamp(entry.time, 0)
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        println!(
+            "{} {:?} {} {}",
+            entry.archive.arcid.cyan(),
+            entry.reason.yellow(),
+            time.bright_black(),
+            entry.archive.title.bright_blue()
+        );
+        if !entry.queries.is_empty() {
+            println!("    搜索: {}", entry.queries.join(" | "));
+        }
+        if let Some(message) = &entry.message {
+            println!("    {}", message.red());
+        }
+    }
+    println!("共 {} 条", count.bright_green());
+}
+
+pub async fn queue_command(
+    settings: &Settings,
+    action: &QueueAction,
+    options: &RunArgs,
+) -> Result<(), String> {
+    match action {
+        QueueAction::List { reason } => print_queue(*reason),
+        QueueAction::Retry => retry(settings, options).await?,
+        QueueAction::Ignore { arcids } => {
+            settings.require(false)?;
+            let archives: Vec<Archive> = Archive::fetch_archives(settings)
+                .await
+                .into_iter()
+                .filter(|archive| arcids.contains(&archive.arcid))
+                .collect();
+            for arcid in arcids {
+                if !archives.iter().any(|archive| &archive.arcid == arcid) {
+                    println!("找不到作品 {}", arcid.bright_yellow());
+                }
+            }
+            ignore_no_handle(&archives);
+            println!("已忽略 {} 条作品", archives.len().bright_green());
+        }
+        QueueAction::Clear { reason } => {
+            let count = clear_no_handle(*reason);
+            println!("已清除 {} 条", count.bright_green());
+        }
+    }
+    Ok(())
+}
+
+/// 按批次或作品撤销写入
+pub async fn revert(
+    settings: &Settings,
+    batch: Option<&str>,
+    arcid: Option<&str>,
+    options: &EditOptions,
+) -> Result<(), String> {
+    let journal = read_journal();
+    if batch.is_none() && arcid.is_none() {
+        print_batches(&journal);
+        return Ok(());
+    }
+    settings.require(false)?;
+    let archives = Archive::fetch_archives(settings).await;
+    let changes = match (batch, arcid) {
+        (Some(batch), _) => undo_changes(&archives, &journal, batch),
+        (None, Some(arcid)) => revert_archive(&archives, &journal, arcid),
+        (None, None) => unreachable!(),
+    };
+    apply(settings, changes, options).await;
+    Ok(())
+}
+
+/// 打印工具对作品做过的所有操作
+pub fn print_history(arcid: &str) {
+    let events = history(arcid);
+    let queued = all_entries().into_iter().find(|e| e.archive.arcid == arcid);
+    if events.is_empty() && queued.is_none() {
+        println!("没有作品 {} 的记录", arcid.bright_yellow());
+        return;
+    }
+    for (time, event) in events {
+        let time = DateTime::from_timestamp(time, 0)
+            .map(|t| {
+                t.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        print!("{} ", time.bright_black());
+        match event {
+            HistoryEvent::Snapshot { title, tags } => {
+                println!("{} {}", "快照".cyan(), title.bright_blue());
+                if !tags.is_empty() {
+                    println!("    {}", tags);
+                }
+            }
+            HistoryEvent::Search {
+                query,
+                result_count,
+                error,
+                candidates,
+            } => {
+                println!("{} {} ({} 条结果)", "搜索".cyan(), query, result_count);
+                if let Some(error) = error {
+                    println!("    {}", error.red());
+                }
+                for (i, (url, title, score)) in candidates.iter().enumerate() {
+                    println!(
+                        "    {} {:.2} {} {}",
+                        (i + 1).cyan(),
+                        score.bright_green(),
+                        title.magenta(),
+                        url.bright_black()
+                    );
+                }
+            }
+            HistoryEvent::Decision {
+                action,
+                url,
+                plugin,
+            } => {
+                let target = url
+                    .or(plugin.map(|plugin| format!("插件 {}", plugin)))
+                    .unwrap_or_default();
+                println!("{} {} {}", "决定".cyan(), action.yellow(), target);
+            }
+            HistoryEvent::Write {
+                batch,
+                old_tags,
+                new_tags,
+            } => {
+                println!("{} 批次 {}", "写入".cyan(), batch.yellow());
+                println!("    {} {}", "-".red(), old_tags);
+                println!("    {} {}", "+".green(), new_tags);
+            }
+        }
+    }
+    if let Some(entry) = queued {
+        println!("当前在未处理列表中: {:?}", entry.reason.yellow());
+    }
+}
+
+pub async fn stats(settings: &Settings) -> Result<(), String> {
+    settings.require(false)?;
+    let archives = Archive::fetch_archives(settings).await;
+    let untagged = archives
+        .iter()
+        .filter(|a| settings.untagged.is_untagged(a))
+        .count();
+    let with_source = archives
+        .iter()
+        .filter(|a| a.tag_list().iter().any(|tag| tag.starts_with("source:")))
+        .count();
+    let is_new = archives.iter().filter(|a| a.isnew == "true").count();
+
+    let mut namespaces: HashMap<String, usize> = HashMap::new();
+    for archive in &archives {
+        for tag in archive.tag_list() {
+            let namespace = tag.split_once(':').map(|(ns, _)| ns).unwrap_or("(无)");
+            *namespaces.entry(namespace.to_string()).or_default() += 1;
+        }
+    }
+    let mut namespaces: Vec<_> = namespaces.into_iter().collect();
+    namespaces.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let journal = read_journal();
+    // 继续运行时沿用旧批次号，同一批次的写入不一定相邻
+    let batches: HashSet<&str> = journal.iter().map(|e| e.batch.as_str()).collect();
+
+    println!("作品总数     {}", archives.len().bright_green());
+    println!("无标签       {}", untagged.bright_yellow());
+    println!("有source标签 {}", with_source.cyan());
+    println!("新作品       {}", is_new.cyan());
+    let queue = all_entries();
+    let ignored = queue
+        .iter()
+        .filter(|entry| entry.reason == Reason::Ignored)
+        .count();
+    println!(
+        "未处理列表   {} (已忽略 {})",
+        (queue.len() - ignored).cyan(),
+        ignored.cyan()
+    );
+    println!(
+        "写入记录     {} 次 / {} 批",
+        journal.len().cyan(),
+        batches.len().cyan()
+    );
+    println!("常用namespace:");
+    for (namespace, count) in namespaces.iter().take(10) {
+        println!("  {:<12} {}", namespace, count);
+    }
+    Ok(())
+}
+
+fn report(name: &str, result: Result<String, String>) -> bool {
+    match result {
+        Ok(msg) => {
+            println!("✅ {} {}", name, msg.bright_black());
+            true
+        }
+        Err(msg) => {
+            println!("❌ {} {}", name, msg.red());
+            false
+        }
+    }
+}
+
+async fn check_lanraragi(settings: &Settings) -> Result<String, String> {
+    let resp = reqwest::Client::new()
+        .get(format!("http://{}/api/info", &settings.endpoint))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let info: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
+    Ok(format!(
+        "version {}",
+        info["version"].as_str().unwrap_or_default()
+    ))
+}
+
+async fn check_api_key(settings: &Settings) -> Result<String, String> {
+    let resp = reqwest::Client::new()
+        .get(format!("http://{}/api/shinobu", &settings.endpoint))
+        .query(&[("key", &settings.api_key)])
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(String::new())
+    } else {
+        Err(format!("HTTP {}", resp.status()))
+    }
+}
+
+async fn check_eh(settings: &Settings) -> Result<String, String> {
+    let resp = fetch_eh(settings, &format!("https://{}/", &settings.site))
+        .await
+        .map_err(|e| e.to_string())?;
+    let text = resp.text().await.map_err(|e| e.to_string())?;
+    // cookies 无效时 exhentai 返回空白页
+    if text.contains("itg") {
+        Ok(settings.site.clone())
+    } else {
+        Err("cookies无效或没有访问权限".to_string())
+    }
+}
+
+async fn check_tag_db(settings: &Settings) -> Result<String, String> {
+    let resp = reqwest::Client::new()
+        .head(settings.db_format.url())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if resp.status().is_success() {
+        Ok(settings.db_format.url())
+    } else {
+        Err(format!("HTTP {}", resp.status()))
+    }
+}
+
+/// 依次检查各项配置和服务，返回是否全部通过
+pub async fn doctor(settings: &Settings) -> bool {
+    let mut ok = report(
+        "配置",
+        settings.require(true).map(|_| {
+            settings
+                .config_path
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default()
+        }),
+    );
+    if !settings.endpoint.is_empty() {
+        ok &= report("LANraragi", check_lanraragi(settings).await);
+        ok &= report("API key", check_api_key(settings).await);
+    }
+    if !settings.cookies.is_empty() {
+        ok &= report("EH", check_eh(settings).await);
+    }
+    ok &= report("标签数据库", check_tag_db(settings).await);
+    if let Some(path) = &settings.overrides {
+        ok &= report(
+            "覆盖词典",
+            TagOverrides::load(path)
+                .map(|_| path.display().to_string())
+                .map_err(|e| e.to_string()),
+        );
+    }
+    ok
+}
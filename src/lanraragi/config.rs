@@ -0,0 +1,429 @@
+use super::args::CommonArgs;
+use super::score::Scoring;
+use super::tag::DbFormat;
+use super::untagged::UntaggedRule;
+
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// 配置文件中的一个 LANraragi 服务器
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    pub api_key_file: Option<PathBuf>,
+    pub cookies: Option<String>,
+    pub cookies_file: Option<PathBuf>,
+    pub site: Option<String>,
+    pub db_format: Option<DbFormat>,
+    pub overrides: Option<PathBuf>,
+    pub scoring: Option<Scoring>,
+    pub untagged: Option<UntaggedRule>,
+    pub tagged_category: Option<String>,
+    pub unhandled_category: Option<String>,
+    pub clear_new: Option<bool>,
+    pub plugins: Option<Vec<String>>,
+    pub hash_search: Option<bool>,
+    /// EH 请求的最小间隔，毫秒
+    pub request_interval: Option<u64>,
+}
+
+/// ```toml
+/// default_profile = "home"
+///
+/// [profiles.home]
+/// endpoint = "192.168.0.1:3000"
+/// api_key_file = "~/.config/lantag_c/api_key"
+/// cookies_file = "~/cookies.txt"
+///
+/// [profiles.home.scoring]
+/// auto = 0.9
+///
+/// [profiles.home.untagged]
+/// ignore_namespaces = ["date_added", "artist"]
+/// min_tags = 1
+/// missing_source = false
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// 按 命令行 > 环境变量 > profile > 默认值 合并后的配置
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub config_path: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub endpoint: String,
+    pub api_key: String,
+    pub cookies: String,
+    pub site: String,
+    pub db_format: DbFormat,
+    pub overrides: Option<PathBuf>,
+    pub scoring: Scoring,
+    pub untagged: UntaggedRule,
+    /// 写入标签后加入的分类
+    pub tagged_category: Option<String>,
+    /// 未处理的作品加入的分类
+    pub unhandled_category: Option<String>,
+    /// 写入标签后清除新作品标记
+    pub clear_new: bool,
+    /// 搜索失败时尝试的 LANraragi 插件，为空时不尝试
+    pub plugins: Vec<String>,
+    /// 标题搜索失败时按页面哈希搜索
+    pub hash_search: bool,
+    pub request_interval: Duration,
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let path = dirs::config_dir()?.join("lantag_c").join("config.toml");
+    path.exists().then_some(path)
+}
+
+// 从文件读取密钥，去掉首尾空白
+fn read_secret(path: &Path) -> Result<String, String> {
+    read_to_string(expand_home(path))
+        .map(|text| text.trim().to_string())
+        .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))
+}
+
+/// Netscape cookies.txt 中取出 EH 相关的 cookie，否则原样返回
+fn parse_cookies(text: &str) -> String {
+    let pairs: Vec<String> = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 || !fields[0].contains("hentai.org") {
+                return None;
+            }
+            Some(format!("{}={}", fields[5], fields[6].trim()))
+        })
+        .collect();
+    if pairs.is_empty() {
+        text.trim().to_string()
+    } else {
+        pairs.join("; ")
+    }
+}
+
+fn read_cookies(path: &Path) -> Result<String, String> {
+    read_to_string(expand_home(path))
+        .map(|text| parse_cookies(&text))
+        .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// 按顺序取第一个设置了值或文件的来源，只读取用到的那个文件
+fn secret(
+    sources: [(Option<String>, Option<PathBuf>); 3],
+    read: fn(&Path) -> Result<String, String>,
+) -> Result<String, String> {
+    for (value, file) in sources {
+        if let Some(value) = value {
+            return Ok(value);
+        }
+        if let Some(path) = file {
+            return read(&path);
+        }
+    }
+    Ok(String::new())
+}
+
+/// 开关的取值：`--no-…` > 命令行或环境变量 > profile，都没有时关闭
+fn switch(value: Option<bool>, off: bool, profile: Option<bool>) -> bool {
+    if off {
+        return false;
+    }
+    value.or(profile).unwrap_or(false)
+}
+
+impl Settings {
+    pub fn resolve(args: &CommonArgs) -> Result<Self, String> {
+        let config_path = match &args.config {
+            Some(path) => Some(expand_home(path)),
+            None => default_config_path(),
+        };
+        let config = match &config_path {
+            Some(path) => {
+                let text = read_to_string(path)
+                    .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+                toml::from_str::<ConfigFile>(&text)
+                    .map_err(|e| format!("解析 {} 失败: {}", path.display(), e))?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let profile_name = args
+            .profile
+            .clone()
+            .or(config.default_profile.clone())
+            .or_else(|| {
+                config
+                    .profiles
+                    .contains_key("default")
+                    .then(|| "default".into())
+            });
+        let empty = Profile::default();
+        let profile = match &profile_name {
+            Some(name) => config
+                .profiles
+                .get(name)
+                .ok_or_else(|| format!("配置中没有 profile: {}", name))?,
+            None => &empty,
+        };
+
+        let mut scoring = profile.scoring.clone().unwrap_or_default();
+        if args.auto.is_some() {
+            scoring.auto = args.auto;
+        }
+        let mut untagged = profile.untagged.clone().unwrap_or_default();
+        if let Some(min_tags) = args.untagged_min_tags {
+            untagged.min_tags = min_tags;
+        }
+        if !args.untagged_ignore_namespace.is_empty() {
+            untagged.ignore_namespaces = args.untagged_ignore_namespace.clone();
+        }
+
+        Ok(Self {
+            config_path,
+            profile: profile_name,
+            endpoint: args
+                .endpoint
+                .clone()
+                .or(profile.endpoint.clone())
+                .unwrap_or_default(),
+            api_key: secret(
+                [
+                    (args.api_key.clone(), args.api_key_file.clone()),
+                    (
+                        env_var("LANTAG_API_KEY"),
+                        env_var("LANTAG_API_KEY_FILE").map(PathBuf::from),
+                    ),
+                    (profile.api_key.clone(), profile.api_key_file.clone()),
+                ],
+                read_secret,
+            )?,
+            cookies: secret(
+                [
+                    (args.cookies.clone(), args.cookies_file.clone()),
+                    (
+                        env_var("LANTAG_COOKIES"),
+                        env_var("LANTAG_COOKIES_FILE").map(PathBuf::from),
+                    ),
+                    (profile.cookies.clone(), profile.cookies_file.clone()),
+                ],
+                read_cookies,
+            )?,
+            site: args
+                .site
+                .clone()
+                .or(profile.site.clone())
+                .unwrap_or_else(|| "exhentai.org".to_string()),
+            db_format: args
+                .db_format
+                .or(profile.db_format)
+                .unwrap_or(DbFormat::Text),
+            overrides: args
+                .overrides
+                .clone()
+                .or(profile.overrides.clone())
+                .map(|path| expand_home(&path)),
+            scoring,
+            untagged,
+            tagged_category: args
+                .tagged_category
+                .clone()
+                .or(profile.tagged_category.clone()),
+            unhandled_category: args
+                .unhandled_category
+                .clone()
+                .or(profile.unhandled_category.clone()),
+            clear_new: switch(args.clear_new, args.no_clear_new, profile.clear_new),
+            hash_search: switch(args.hash_search, args.no_hash_search, profile.hash_search),
+            plugins: if args.plugins.is_empty() {
+                profile.plugins.clone().unwrap_or_default()
+            } else {
+                args.plugins.clone()
+            },
+            request_interval: Duration::from_millis(
+                args.request_interval
+                    .or(profile.request_interval)
+                    .unwrap_or(500),
+            ),
+        })
+    }
+
+    /// 检查连接所需的配置是否齐全
+    pub fn require(&self, need_cookies: bool) -> Result<(), String> {
+        let mut missing = vec![];
+        if self.endpoint.is_empty() {
+            missing.push("endpoint");
+        }
+        if self.api_key.is_empty() {
+            missing.push("api_key");
+        }
+        if need_cookies && self.cookies.is_empty() {
+            missing.push("cookies");
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("缺少配置: {}", missing.join(", ")))
+        }
+    }
+
+    pub fn print(&self) {
+        // 只显示是否设置，不泄露任何字符
+        fn mask(secret: &str) -> String {
+            if secret.is_empty() {
+                return "(未设置)".to_string();
+            }
+            "********".to_string()
+        }
+        fn or_unset(value: Option<String>) -> String {
+            value.unwrap_or_else(|| "(未设置)".to_string())
+        }
+        let rows = [
+            (
+                "config",
+                or_unset(self.config_path.as_ref().map(|p| p.display().to_string())),
+            ),
+            ("profile", or_unset(self.profile.clone())),
+            (
+                "endpoint",
+                or_unset((!self.endpoint.is_empty()).then(|| self.endpoint.clone())),
+            ),
+            ("api_key", mask(&self.api_key)),
+            ("cookies", mask(&self.cookies)),
+            ("site", self.site.clone()),
+            ("db_format", format!("{:?}", self.db_format).to_lowercase()),
+            (
+                "overrides",
+                or_unset(self.overrides.as_ref().map(|p| p.display().to_string())),
+            ),
+            (
+                "scoring",
+                format!(
+                    "similarity_weight={} page_weight={} auto={}",
+                    self.scoring.similarity_weight,
+                    self.scoring.page_weight,
+                    or_unset(self.scoring.auto.map(|a| a.to_string()))
+                ),
+            ),
+            (
+                "untagged",
+                format!(
+                    "ignore_namespaces=[{}] min_tags={} missing_source={}",
+                    self.untagged.ignore_namespaces.join(","),
+                    self.untagged.min_tags,
+                    self.untagged.missing_source
+                ),
+            ),
+            ("tagged_category", or_unset(self.tagged_category.clone())),
+            (
+                "unhandled_category",
+                or_unset(self.unhandled_category.clone()),
+            ),
+            ("clear_new", self.clear_new.to_string()),
+            ("hash_search", self.hash_search.to_string()),
+            (
+                "plugins",
+                or_unset((!self.plugins.is_empty()).then(|| self.plugins.join(","))),
+            ),
+            (
+                "request_interval",
+                format!("{}ms", self.request_interval.as_millis()),
+            ),
+        ];
+        for (key, value) in rows {
+            println!("{:<10} {}", key.cyan(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing() -> Option<PathBuf> {
+        Some(PathBuf::from("/nonexistent/lantag_c/secret"))
+    }
+
+    #[test]
+    fn cli_file_beats_env_value() {
+        let file = std::env::temp_dir().join("lantag_c_test_api_key");
+        std::fs::write(&file, " from-file \n").unwrap();
+        let value = secret(
+            [
+                (None, Some(file.clone())),
+                (Some("from-env".to_string()), None),
+                (None, None),
+            ],
+            read_secret,
+        );
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(value.unwrap(), "from-file");
+    }
+
+    #[test]
+    fn unused_profile_file_is_not_read() {
+        let value = secret(
+            [
+                (Some("cli".to_string()), None),
+                (None, None),
+                (None, missing()),
+            ],
+            read_secret,
+        );
+        assert_eq!(value.unwrap(), "cli");
+        assert!(secret([(None, None), (None, None), (None, missing())], read_secret).is_err());
+        assert_eq!(
+            secret([(None, None), (None, None), (None, None)], read_secret).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn parse_netscape_cookies() {
+        let text = "# Netscape HTTP Cookie File\n\
+                    .exhentai.org\tTRUE\t/\tFALSE\t0\tigneous\tabc\n\
+                    .example.com\tTRUE\t/\tFALSE\t0\tother\tx\n\
+                    .e-hentai.org\tTRUE\t/\tFALSE\t0\tipb_member_id\t42\n";
+        assert_eq!(parse_cookies(text), "igneous=abc; ipb_member_id=42");
+    }
+
+    #[test]
+    fn parse_plain_cookies() {
+        assert_eq!(
+            parse_cookies(" igneous=abc; ipb_pass_hash=x\n"),
+            "igneous=abc; ipb_pass_hash=x"
+        );
+    }
+
+    #[test]
+    fn switch_can_turn_profile_off() {
+        assert!(switch(None, false, Some(true)));
+        assert!(!switch(None, true, Some(true)));
+        assert!(!switch(Some(false), false, Some(true)));
+        assert!(switch(Some(true), false, Some(false)));
+        assert!(!switch(None, false, None));
+    }
+}
@@ -1,20 +1,25 @@
 use super::archive::Archive;
-use super::args::args;
+use super::config::Settings;
 use super::error::FetchError;
-use super::utils::fetch_raw_with_retry;
+use super::score::{Features, Scoring};
+use super::tag::{translate_tag, CnTags, TagDictionary};
+use super::untranslated::record_untranslated;
+use super::utils::{fetch_limited_with_retry, RateLimiter};
 
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use owo_colors::OwoColorize;
 use regex::Regex;
-use std::sync::OnceLock;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
 use std::{collections::HashMap, fmt::Debug};
 use strsim::normalized_damerau_levenshtein;
 use tabled::{builder::Builder, settings::Style};
 use tabled::{settings::object::Columns, settings::Format};
-use url::form_urlencoded;
+use url::{form_urlencoded, Url};
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct HenTagItem {
@@ -22,6 +27,7 @@ struct HenTagItem {
     name: String,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct HenTag {
@@ -47,6 +53,7 @@ struct HenTag {
     tags: String,
 }
 
+#[allow(dead_code)]
 fn default_tags() -> String {
     "".to_string()
 }
@@ -62,11 +69,16 @@ pub struct GL {
     url: String,
 }
 
-async fn fetch_eh(url: &str) -> Result<reqwest::Response, FetchError> {
-    fetch_raw_with_retry(
+/// 所有 EH 请求共用的频率限制
+static EH_LIMITER: RateLimiter = RateLimiter::new();
+
+pub async fn fetch_eh(settings: &Settings, url: &str) -> Result<reqwest::Response, FetchError> {
+    fetch_limited_with_retry(
+        &EH_LIMITER,
+        settings.request_interval,
         || reqwest::Client::new()
         .get(url)
-        .header("Cookie", &args().cookies)
+        .header("Cookie", &settings.cookies)
         .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/119.0.0.0 Safari/537.36 Edg/119.0.0.0")
     ).await
 }
@@ -80,116 +92,170 @@ fn remove_unsupport_str(intput: &str) -> String {
     regex_unsupport().replace_all(intput, "&").to_string()
 }
 
-impl Archive {
-    pub async fn search_from_eh(&self) -> Vec<GL> {
-        let title = self.regex_title();
-        if title.is_empty() {
-            println!("❌title no match: {}", &self.title.red());
-            return vec![];
-        }
-        let url = "https://exhentai.org/?f_search=";
-        let url = format!(
-            "{}{}",
-            url,
-            form_urlencoded::byte_serialize(remove_unsupport_str(&title).as_bytes())
-                .collect::<String>()
-        );
-        let resp = fetch_eh(&url).await.unwrap();
+fn regex_gallery_path() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"^/g/\d+/[0-9a-f]+/?$").unwrap())
+}
 
-        let text = resp.text().await.unwrap();
-        let doucment = Html::parse_document(&text);
-        let trs_selector = Selector::parse("table.itg.gltc tr").unwrap();
-        let trs = doucment.select(&trs_selector);
+/// 检查是否为 EH 画廊地址，去掉 `?p=1` 之类的查询和锚点
+fn gallery_url(input: &str) -> Option<String> {
+    let url = Url::parse(input).ok()?;
+    if !matches!(url.scheme(), "http" | "https")
+        || !matches!(url.host_str()?, "exhentai.org" | "e-hentai.org")
+        || !regex_gallery_path().is_match(url.path())
+    {
+        return None;
+    }
+    Some(format!(
+        "{}://{}{}",
+        url.scheme(),
+        url.host_str()?,
+        url.path()
+    ))
+}
 
-        let mut frist_flag = true;
+/// 下载搜索结果页并读取其中的画廊列表
+async fn fetch_search_results(settings: &Settings, url: &str) -> Result<Vec<GL>, FetchError> {
+    let resp = fetch_eh(settings, url).await?;
 
-        let mut gls = vec![];
+    let text = resp.text().await?;
+    let doucment = Html::parse_document(&text);
+    let trs_selector = Selector::parse("table.itg.gltc tr").unwrap();
+    let trs = doucment.select(&trs_selector);
 
-        for tr in trs {
-            if frist_flag {
-                frist_flag = false;
-                continue;
-            }
-            let type_ = tr
-                .select(&Selector::parse("td:nth-child(1) div").unwrap())
-                .next()
-                .unwrap()
-                .text()
-                .collect::<String>();
-            let datetime = tr
-                .select(
-                    &Selector::parse("td:nth-child(2) div:nth-child(3) div:nth-child(1)").unwrap(),
-                )
-                .next()
-                .unwrap()
-                .text()
-                .collect::<String>();
-            let url = tr
-                .select(&Selector::parse("td:nth-child(3) a").unwrap())
-                .next()
-                .unwrap()
-                .value()
-                .attr("href")
-                .unwrap()
-                .to_string();
-            let title = tr
-                .select(&Selector::parse("td:nth-child(3) a div:nth-child(1)").unwrap())
-                .next()
-                .unwrap()
-                .text()
-                .collect::<String>();
-            let pages = tr
-                .select(&Selector::parse("td:nth-child(4) div:nth-child(2)").unwrap())
-                .next()
-                .unwrap()
-                .text()
-                .collect::<String>();
-            let tagnods_selector =
-                Selector::parse("td:nth-child(3) a div:nth-child(2) div.gt").unwrap();
-            let tagnods = tr.select(&tagnods_selector);
-            let mut tags = String::new();
-            for tagnode in tagnods {
-                if let Some(title) = tagnode.value().attr("title") {
-                    tags.push_str(title);
-                    tags.push(',');
-                }
-            }
+    let mut frist_flag = true;
+
+    let mut gls = vec![];
 
-            // Remove trailing comma
-            tags.pop();
-
-            gls.push(GL {
-                type_,
-                datetime,
-                tags,
-                title,
-                pages,
-                url,
-            });
+    for tr in trs {
+        if frist_flag {
+            frist_flag = false;
+            continue;
         }
-        if gls.is_empty() {
-            println!("❌no result for: {}", &self.title.red());
+        let type_ = tr
+            .select(&Selector::parse("td:nth-child(1) div").unwrap())
+            .next()
+            .unwrap()
+            .text()
+            .collect::<String>();
+        let datetime = tr
+            .select(&Selector::parse("td:nth-child(2) div:nth-child(3) div:nth-child(1)").unwrap())
+            .next()
+            .unwrap()
+            .text()
+            .collect::<String>();
+        let url = tr
+            .select(&Selector::parse("td:nth-child(3) a").unwrap())
+            .next()
+            .unwrap()
+            .value()
+            .attr("href")
+            .unwrap()
+            .to_string();
+        let title = tr
+            .select(&Selector::parse("td:nth-child(3) a div:nth-child(1)").unwrap())
+            .next()
+            .unwrap()
+            .text()
+            .collect::<String>();
+        let pages = tr
+            .select(&Selector::parse("td:nth-child(4) div:nth-child(2)").unwrap())
+            .next()
+            .unwrap()
+            .text()
+            .collect::<String>();
+        let tagnods_selector =
+            Selector::parse("td:nth-child(3) a div:nth-child(2) div.gt").unwrap();
+        let tagnods = tr.select(&tagnods_selector);
+        let mut tags = String::new();
+        for tagnode in tagnods {
+            if let Some(title) = tagnode.value().attr("title") {
+                tags.push_str(title);
+                tags.push(',');
+            }
         }
-        gls
+
+        // Remove trailing comma
+        tags.pop();
+
+        gls.push(GL {
+            type_,
+            datetime,
+            tags,
+            title,
+            pages,
+            url,
+        });
     }
+    Ok(gls)
+}
 
-    pub fn print_and_get_index(&self, gls: &[GL]) -> i32 {
+impl Archive {
+    /// 用 `query` 搜索 EH，一般为 `regex_title` 的结果
+    pub async fn search_from_eh(
+        &self,
+        settings: &Settings,
+        query: &str,
+    ) -> Result<Vec<GL>, FetchError> {
+        let url = format!(
+            "https://{}/?f_search={}",
+            &settings.site,
+            form_urlencoded::byte_serialize(remove_unsupport_str(query).as_bytes())
+                .collect::<String>()
+        );
+        fetch_search_results(settings, &url).await
+    }
+
+    /// 用页面图片的SHA-1搜索 EH 的文件搜索
+    pub async fn search_by_hash(
+        &self,
+        settings: &Settings,
+        sha1: &str,
+    ) -> Result<Vec<GL>, FetchError> {
+        let url = format!("https://{}/?f_shash={}&fs_exp=on", &settings.site, sha1);
+        fetch_search_results(settings, &url).await
+    }
+    pub fn features(&self, gl: &GL) -> Features {
+        Features {
+            similarity: normalized_damerau_levenshtein(&self.title, &gl.title),
+            page_match: gl.pages.trim_end_matches(" pages") == self.pagecount.to_string(),
+        }
+    }
+
+    /// 显示候选并读取用户的选择，`matches` 为页面哈希搜索时每个候选命中的页数，
+    /// `intro` 不为空时在表格下显示候选标签的中文说明
+    pub fn print_and_choose(
+        &self,
+        gls: &[GL],
+        matches: Option<&[usize]>,
+        scoring: &Scoring,
+        intro: Option<&TagDictionary>,
+    ) -> Choice {
         let mut builder = Builder::default();
-        builder.push_record(["序号", "标题", "相似度", "页数", "日期"]);
+        let mut header = vec!["序号", "标题", "相似度", "页数", "日期", "评分"];
+        if matches.is_some() {
+            header.push("哈希命中");
+        }
+        builder.push_record(header);
         for (i, gl) in gls.iter().enumerate() {
-            let similarity = normalized_damerau_levenshtein(&self.title, &gl.title) * 100.0;
-            let page_flag = if gl.pages.trim_end_matches(" pages") == self.pagecount.to_string() {
+            let features = self.features(gl);
+            let page_flag = if features.page_match {
                 format!("{}✅", &gl.pages)
             } else {
                 (gl.pages).to_string()
             };
-            builder.push_record([
-                &(i as i32 + 1).to_string(),
-                &gl.title,
-                &format!("{:.1}%", similarity).to_string(),
-                &page_flag,
-                &gl.datetime,
-            ]);
+            let mut record = vec![
+                (i as i32 + 1).to_string(),
+                gl.title.clone(),
+                format!("{:.1}%", features.similarity * 100.0),
+                page_flag,
+                gl.datetime.clone(),
+                format!("{:.2}", scoring.score(&features)),
+            ];
+            if let Some(matches) = matches {
+                record.push(format!("{} 页", matches[i]));
+            }
+            builder.push_record(record);
         }
         let mut table = builder.build();
         table
@@ -213,6 +279,10 @@ impl Archive {
             .modify(
                 Columns::single(4),
                 Format::content(|s| s.yellow().to_string()),
+            )
+            .modify(
+                Columns::single(5),
+                Format::content(|s| s.bright_green().to_string()),
             );
         println!(
             "{} - {}",
@@ -220,32 +290,164 @@ impl Archive {
             &self.pagecount.bright_blue()
         );
         println!("{}", table);
-        // get user input
+        if let Some(dict) = intro {
+            print_intro(gls, dict);
+        }
+        read_choice(gls.len())
+    }
+}
+
+/// 用户对候选列表的选择
+pub enum Choice {
+    /// 候选的下标，从 0 开始
+    Select(usize),
+    Skip,
+    /// 保存状态并退出
+    Quit,
+    /// 用新的关键词重新搜索
+    Search(String),
+    /// 直接使用粘贴的画廊
+    Url(GL),
+}
+
+static PROMPT_HELP: &str = "  <序号>        选择对应的候选
+  s 或 0        跳过，加入未处理列表
+  q             保存状态并退出，之后用 --resume 继续
+  /<关键词>     用新的关键词重新搜索
+  <画廊URL>     直接使用该画廊的标签
+  ?             显示帮助";
+
+/// 解析一行输入，无法识别时返回 None
+fn parse_choice(input: &str, len: usize) -> Option<Choice> {
+    match input {
+        "s" | "0" => return Some(Choice::Skip),
+        "q" => return Some(Choice::Quit),
+        _ => {}
+    }
+    if let Some(query) = input.strip_prefix('/') {
+        let query = query.trim();
+        return (!query.is_empty()).then(|| Choice::Search(query.to_string()));
+    }
+    if let Some(gl) = GL::from_url(input) {
+        return Some(Choice::Url(gl));
+    }
+    match input.parse::<usize>() {
+        Ok(index) if index >= 1 && index <= len => Some(Choice::Select(index - 1)),
+        _ => None,
+    }
+}
+
+/// 读取用户输入直到有效，输入结束时视为退出
+fn read_choice(len: usize) -> Choice {
+    loop {
+        print!("选择序号 (s 跳过, q 退出, /关键词 重新搜索, 画廊URL, ? 帮助): ");
+        std::io::stdout().flush().unwrap();
         let mut input = String::new();
-        std::io::stdin()
+        if std::io::stdin()
             .read_line(&mut input)
-            .expect("Failed to read line");
-        input.trim().parse::<i32>().unwrap()
+            .expect("Failed to read line")
+            == 0
+        {
+            return Choice::Quit;
+        }
+        let input = input.trim();
+        if input == "?" {
+            println!("{}", PROMPT_HELP);
+            continue;
+        }
+        match parse_choice(input, len) {
+            Some(choice) => return choice,
+            None => println!("{} 输入 ? 查看帮助", format!("无效的输入: {}", input).red()),
+        }
+    }
+}
+
+fn print_intro(gls: &[GL], dict: &TagDictionary) {
+    let mut seen = vec![];
+    for gl in gls {
+        for tag in gl.tags.split(',') {
+            if seen.contains(&tag) {
+                continue;
+            }
+            seen.push(tag);
+            if let Some(intro) = dict.describe(tag) {
+                println!("{} {}", tag.bright_black(), intro);
+            }
+        }
     }
 }
 
 impl GL {
-    pub async fn get_tags_from_eh_gl(&self, cn_tags: &HashMap<String, String>) -> String {
-        let resp = fetch_eh(&self.url).await.unwrap();
-        let text = resp.text().await.unwrap();
-        let document = Html::parse_document(&text);
-        let tag_bodu_selector = Selector::parse("div#taglist > table a").unwrap();
-        let tag_body = document.select(&tag_bodu_selector);
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn pages(&self) -> &str {
+        &self.pages
+    }
+
+    pub fn datetime(&self) -> &str {
+        &self.datetime
+    }
+
+    /// 搜索结果中显示的原始标签，不一定完整
+    pub fn raw_tags(&self) -> Vec<&str> {
+        self.tags.split(',').filter(|tag| !tag.is_empty()).collect()
+    }
+
+    /// 直接从画廊URL构造，其余信息在获取标签时从画廊页读取
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = gallery_url(url.trim())?;
+        Some(Self {
+            type_: String::new(),
+            datetime: String::new(),
+            tags: String::new(),
+            title: String::new(),
+            pages: String::new(),
+            url,
+        })
+    }
+
+    /// 下载画廊页，已预取的直接从缓存取出
+    pub async fn fetch_gallery(&self, settings: &Settings) -> Result<GalleryPage, FetchError> {
+        if let Some(page) = gallery_cache().lock().unwrap().remove(&self.url) {
+            return Ok(page);
+        }
+        let resp = fetch_eh(settings, &self.url).await?;
+        let text = resp.text().await?;
+        Ok(GalleryPage::parse(&text))
+    }
+
+    /// 后台预先下载画廊页，之后的 `get_tags_from_eh_gl` 不用再等
+    pub async fn prefetch_gallery(&self, settings: &Settings) {
+        if gallery_cache().lock().unwrap().contains_key(&self.url) {
+            return;
+        }
+        if let Ok(page) = self.fetch_gallery(settings).await {
+            gallery_cache()
+                .lock()
+                .unwrap()
+                .insert(self.url.clone(), page);
+        }
+    }
+
+    pub async fn get_tags_from_eh_gl(
+        &self,
+        settings: &Settings,
+        cn_tags: &CnTags,
+    ) -> Result<String, FetchError> {
+        let page = self.fetch_gallery(settings).await?;
         let mut tags_str = String::new();
-        for i in tag_body {
-            let tag = i.value().attr("id").unwrap();
-            let mut raw_tag = String::new();
-            raw_tag.push_str(&tag.trim_start_matches("ta_").replace('_', " "));
-            if let Some(cn_tag) = cn_tags.get(&raw_tag) {
-                tags_str.push_str(cn_tag);
-                tags_str.push(',');
-            } else {
-                tags_str.push_str(&raw_tag);
+        for raw_tag in &page.raw_tags {
+            if !cn_tags.knows(raw_tag) {
+                record_untranslated(raw_tag, &self.url);
+            }
+            if let Some(tag) = translate_tag(cn_tags, raw_tag) {
+                tags_str.push_str(&tag);
                 tags_str.push(',');
             }
         }
@@ -253,10 +455,138 @@ impl GL {
             "source:{}",
             &self.url.trim_start_matches("https://")
         ));
-        let datetime = NaiveDateTime::parse_from_str(&self.datetime, "%Y-%m-%d %H:%M")
-            .expect("failed to parse datetime");
+        let posted = if self.datetime.is_empty() {
+            &page.posted
+        } else {
+            &self.datetime
+        };
+        // 画廊不存在或 cookies 无效时没有信息表
+        let datetime = NaiveDateTime::parse_from_str(posted, "%Y-%m-%d %H:%M")
+            .map_err(|_| FetchError::Other(format!("无法读取画廊 {} 的发布时间", &self.url)))?;
         let utc_time = Utc.from_utc_datetime(&datetime).timestamp();
         tags_str.push_str(&format!(",timestamp:{}", utc_time));
-        tags_str
+        Ok(tags_str)
+    }
+}
+
+/// 画廊页中用到的信息
+#[derive(Debug, Clone)]
+pub struct GalleryPage {
+    pub raw_tags: Vec<String>,
+    /// 信息表中的 Posted
+    pub posted: String,
+    /// 父画廊和更新版本画廊的URL
+    pub related: Vec<String>,
+}
+
+impl GalleryPage {
+    fn parse(text: &str) -> Self {
+        let document = Html::parse_document(text);
+        let tag_bodu_selector = Selector::parse("div#taglist > table a").unwrap();
+        let raw_tags = document
+            .select(&tag_bodu_selector)
+            .filter_map(|a| a.value().attr("id"))
+            .map(|id| id.trim_start_matches("ta_").replace('_', " "))
+            .collect();
+        // 画廊页信息表的第一行为 Posted
+        let posted = document
+            .select(&Selector::parse("#gdd td.gdt2").unwrap())
+            .next()
+            .map(|td| td.text().collect::<String>())
+            .unwrap_or_default();
+        // 信息表中 Parent 一行的链接，以及页面顶部列出的更新版本
+        let row_selector = Selector::parse("#gdd tr").unwrap();
+        let link_selector = Selector::parse("td.gdt2 a").unwrap();
+        let newer_selector = Selector::parse("#gnd a").unwrap();
+        let mut related: Vec<String> = document
+            .select(&row_selector)
+            .filter(|tr| tr.text().any(|text| text.starts_with("Parent")))
+            .flat_map(|tr| tr.select(&link_selector).collect::<Vec<_>>())
+            .chain(document.select(&newer_selector))
+            .filter_map(|a| a.value().attr("href"))
+            .map(str::to_string)
+            .collect();
+        related.dedup();
+        Self {
+            raw_tags,
+            posted,
+            related,
+        }
+    }
+}
+
+/// 预取的画廊页，key 为画廊 URL
+fn gallery_cache() -> &'static Mutex<HashMap<String, GalleryPage>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, GalleryPage>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// 作品处理完后丢弃为它预取但没有用到的画廊页
+pub fn evict_galleries<'a>(urls: impl IntoIterator<Item = &'a str>) {
+    let mut cache = gallery_cache().lock().unwrap();
+    for url in urls {
+        cache.remove(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gallery_url_ignores_query_and_fragment() {
+        assert_eq!(
+            gallery_url("https://exhentai.org/g/123/abcdef/?p=1#comments").unwrap(),
+            "https://exhentai.org/g/123/abcdef/"
+        );
+        assert_eq!(
+            gallery_url("http://e-hentai.org/g/123/abcdef").unwrap(),
+            "http://e-hentai.org/g/123/abcdef"
+        );
+        assert!(gallery_url("https://example.org/g/123/abcdef/").is_none());
+        assert!(gallery_url("https://exhentai.org/s/abcdef/123-1").is_none());
+        assert!(gallery_url("exhentai.org/g/123/abcdef/").is_none());
+    }
+
+    #[test]
+    fn parse_choice_inputs() {
+        assert!(matches!(parse_choice("s", 3), Some(Choice::Skip)));
+        assert!(matches!(parse_choice("0", 3), Some(Choice::Skip)));
+        assert!(matches!(parse_choice("q", 3), Some(Choice::Quit)));
+        assert!(matches!(parse_choice("2", 3), Some(Choice::Select(1))));
+        assert!(parse_choice("4", 3).is_none());
+        assert!(parse_choice("x", 3).is_none());
+        assert!(parse_choice("/  ", 3).is_none());
+        assert!(matches!(
+            parse_choice("/ new query ", 3),
+            Some(Choice::Search(query)) if query == "new query"
+        ));
+        assert!(matches!(
+            parse_choice("https://e-hentai.org/g/1/ab/?p=2", 3),
+            Some(Choice::Url(gl)) if gl.url() == "https://e-hentai.org/g/1/ab/"
+        ));
+    }
+
+    #[test]
+    fn gallery_page_parse() {
+        let html = r#"<html><body>
+            <div id="gnd">Newer Version: <a href="https://e-hentai.org/g/3/c/">v3</a></div>
+            <div id="gdd"><table>
+                <tr><td class="gdt1">Posted:</td><td class="gdt2">2024-01-02 03:04</td></tr>
+                <tr><td class="gdt1">Parent:</td><td class="gdt2"><a href="https://e-hentai.org/g/1/a/">1</a></td></tr>
+                <tr><td class="gdt1">Language:</td><td class="gdt2"><a href="https://e-hentai.org/x">x</a></td></tr>
+            </table></div>
+            <div id="taglist"><table><tr><td>
+                <a id="ta_artist:some_one">some one</a>
+                <a id="ta_female:big_breasts">big breasts</a>
+            </td></tr></table></div>
+        </body></html>"#;
+        let page = GalleryPage::parse(html);
+        assert_eq!(page.raw_tags, ["artist:some one", "female:big breasts"]);
+        assert_eq!(page.posted, "2024-01-02 03:04");
+        assert_eq!(
+            page.related,
+            ["https://e-hentai.org/g/1/a/", "https://e-hentai.org/g/3/c/"]
+        );
     }
 }
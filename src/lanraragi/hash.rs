@@ -0,0 +1,194 @@
+use super::archive::Archive;
+use super::commands::Candidates;
+use super::config::Settings;
+use super::eh::GL;
+use super::error::FetchError;
+use super::store::{record_candidates, record_search};
+use super::utils::fetch_raw_with_retry;
+
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// 计算哈希的页数，从第一页开始
+static HASH_PAGES: usize = 3;
+
+#[derive(Debug, Deserialize)]
+struct Files {
+    pages: Vec<String>,
+}
+
+/// 页面哈希搜索结果中保存的搜索词
+pub static HASH_QUERY: &str = "sha1";
+
+/// 页面哈希搜索的命中情况，与候选一一对应，候选按命中的页数从多到少排列
+pub struct HashMatches {
+    /// 每个候选命中的页数
+    pub matches: Vec<usize>,
+    /// 实际计算了哈希的页数
+    pub hashed: usize,
+}
+
+impl HashMatches {
+    /// 唯一一个命中多页(只有一页时为该页)的画廊，可以直接采用
+    pub fn confident(&self) -> Option<usize> {
+        let best = *self.matches.first()?;
+        let unique = self.matches.get(1).is_none_or(|&second| second < best);
+        (unique && best >= self.hashed.min(2)).then_some(0)
+    }
+}
+
+/// 作品前几页图片的下载地址
+async fn page_urls(settings: &Settings, arcid: &str) -> Result<Vec<String>, FetchError> {
+    let resp = fetch_raw_with_retry(|| {
+        reqwest::Client::new()
+            .get(format!(
+                "http://{}/api/archives/{}/files",
+                &settings.endpoint, arcid
+            ))
+            .query(&[("key", &settings.api_key)])
+    })
+    .await?;
+    let files = resp.json::<Files>().await?;
+    Ok(files
+        .pages
+        .iter()
+        .take(HASH_PAGES)
+        // 返回的是相对路径，如 ./api/archives/<id>/page?path=001.jpg
+        .map(|page| {
+            format!(
+                "http://{}/{}",
+                &settings.endpoint,
+                page.trim_start_matches("./").trim_start_matches('/')
+            )
+        })
+        .collect())
+}
+
+async fn page_hash(settings: &Settings, url: &str) -> Result<String, FetchError> {
+    let resp = fetch_raw_with_retry(|| {
+        reqwest::Client::new()
+            .get(url)
+            .query(&[("key", &settings.api_key)])
+    })
+    .await?;
+    let bytes = resp.bytes().await?;
+    Ok(sha1_smol::Sha1::from(&bytes).digest().to_string())
+}
+
+/// 下载作品的前几页并计算SHA-1，用 EH 的文件搜索找出包含这些图片的画廊；
+/// 会在后台调用，不输出
+pub async fn hash_search(settings: &Settings, archive: &Archive) -> Result<Candidates, FetchError> {
+    let mut hashes = vec![];
+    for url in page_urls(settings, &archive.arcid).await? {
+        hashes.push(page_hash(settings, &url).await?);
+    }
+    if hashes.is_empty() {
+        return Err(FetchError::Other("作品没有页面".to_string()));
+    }
+
+    // 同一画廊可能被多个哈希找到，保留第一次出现的顺序
+    let mut found: Vec<(GL, usize)> = vec![];
+    for hash in &hashes {
+        for gl in archive.search_by_hash(settings, hash).await? {
+            match found.iter_mut().find(|(seen, _)| seen.url() == gl.url()) {
+                Some((_, count)) => *count += 1,
+                None => found.push((gl, 1)),
+            }
+        }
+    }
+    found.sort_by_key(|(_, count)| Reverse(*count));
+
+    let query = format!("sha1:{}", hashes.join(","));
+    let search_id = record_search(&archive.arcid, &query, found.len(), None);
+    let (gls, matches): (Vec<GL>, Vec<usize>) = found.into_iter().unzip();
+    let features: Vec<_> = gls.iter().map(|gl| archive.features(gl)).collect();
+    let recorded: Vec<_> = gls
+        .iter()
+        .zip(&features)
+        .map(|(gl, f)| (gl.url(), gl.title(), *f))
+        .collect();
+    record_candidates(search_id, &recorded, &settings.scoring);
+    Ok(Candidates {
+        search_id,
+        gls,
+        features,
+        hash: Some(HashMatches {
+            matches,
+            hashed: hashes.len(),
+        }),
+    })
+}
+
+/// 标题搜索没有结果时改用页面哈希搜索，`result` 为 None 表示标题无法提取关键词；
+/// 没有开启或哈希也没有找到时保留原来的结果。哈希搜索失败只记录在搜索历史中，不输出
+pub async fn or_hash_search(
+    settings: &Settings,
+    archive: &Archive,
+    result: Option<Result<Candidates, FetchError>>,
+) -> Option<Result<Candidates, FetchError>> {
+    let failed = match &result {
+        Some(Ok(candidates)) => candidates.gls.is_empty(),
+        _ => true,
+    };
+    if !settings.hash_search || !failed {
+        return result;
+    }
+    match hash_search(settings, archive).await {
+        Ok(found) if !found.gls.is_empty() => Some(Ok(found)),
+        Ok(_) => result,
+        Err(err) => {
+            record_search(&archive.arcid, HASH_QUERY, 0, Some(&err.to_string()));
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lanraragi::score::{Features, Scoring};
+
+    fn candidates(similarity: &[f64], hash: Option<(Vec<usize>, usize)>) -> Candidates {
+        Candidates {
+            search_id: 1,
+            gls: vec![],
+            features: similarity
+                .iter()
+                .map(|&similarity| Features {
+                    similarity,
+                    page_match: false,
+                })
+                .collect(),
+            hash: hash.map(|(matches, hashed)| HashMatches { matches, hashed }),
+        }
+    }
+
+    #[test]
+    fn confident_needs_unique_multi_page_match() {
+        let confident = |matches: Vec<usize>, hashed| HashMatches { matches, hashed }.confident();
+        assert_eq!(confident(vec![3, 1], 3), Some(0));
+        assert_eq!(confident(vec![2], 3), Some(0));
+        assert_eq!(confident(vec![2, 2], 3), None);
+        assert_eq!(confident(vec![1], 3), None);
+        // 只有一页时命中该页即可
+        assert_eq!(confident(vec![1], 1), Some(0));
+        assert_eq!(confident(vec![], 3), None);
+    }
+
+    #[test]
+    fn hash_results_select_by_matches_not_title() {
+        let scoring = Scoring {
+            auto: Some(0.5),
+            ..Scoring::default()
+        };
+        // 标题相似度高的第二个候选只命中一页，不能被选中
+        let found = candidates(&[0.1, 1.0], Some((vec![3, 1], 3)));
+        assert_eq!(found.auto_select(&scoring), Some(0));
+        let tied = candidates(&[0.1, 1.0], Some((vec![2, 2], 3)));
+        assert_eq!(tied.auto_select(&scoring), None);
+        let by_title = candidates(&[0.1, 1.0], None);
+        assert_eq!(by_title.auto_select(&scoring), Some(1));
+        // 没有开启自动选择时哈希结果也需要手动选择
+        assert_eq!(found.auto_select(&Scoring::default()), None);
+    }
+}
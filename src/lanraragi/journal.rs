@@ -0,0 +1,51 @@
+use super::store::store;
+
+use chrono::Local;
+
+use serde::Deserialize;
+use std::sync::{OnceLock, PoisonError};
+
+/// 每次写入 LANraragi 前后的标签，用于撤销，保存在数据库的 writes 表中
+#[derive(Debug, Clone, Deserialize)]
+pub struct JournalEntry {
+    pub batch: String,
+    pub arcid: String,
+    pub old_tags: String,
+    pub new_tags: String,
+    pub time: i64,
+}
+
+fn batch_id_cell() -> &'static OnceLock<String> {
+    static BATCH_ID: OnceLock<String> = OnceLock::new();
+    &BATCH_ID
+}
+
+/// 本次运行的批次号，同一次运行的写入可以一起撤销
+pub fn batch_id() -> &'static str {
+    batch_id_cell().get_or_init(|| Local::now().format("%Y%m%d-%H%M%S").to_string())
+}
+
+/// 继续上次的运行时沿用它的批次号，必须在第一次写入前调用
+pub fn resume_batch_id(id: &str) {
+    let _ = batch_id_cell().set(id.to_string());
+}
+
+/// 按时间顺序返回所有写入记录
+pub fn read_journal() -> Vec<JournalEntry> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut stmt = conn
+        .prepare("SELECT batch, arcid, old_tags, new_tags, time FROM writes ORDER BY time, id")
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(JournalEntry {
+                batch: row.get(0)?,
+                arcid: row.get(1)?,
+                old_tags: row.get(2)?,
+                new_tags: row.get(3)?,
+                time: row.get(4)?,
+            })
+        })
+        .unwrap();
+    rows.map(Result::unwrap).collect()
+}
@@ -0,0 +1,180 @@
+use super::archive::{split_tags, Archive};
+use super::args::{EditOptions, TagsAction};
+use super::config::Settings;
+use super::journal::{read_journal, JournalEntry};
+use super::rewrite::{apply_changes, TagChange};
+
+use owo_colors::OwoColorize;
+use regex::Regex;
+use std::collections::HashMap;
+
+fn edit_library<F>(archives: &[Archive], edit: F) -> Vec<TagChange>
+where
+    F: Fn(Vec<String>) -> Vec<String>,
+{
+    archives
+        .iter()
+        .filter_map(|archive| TagChange::new(archive, edit(archive.tag_list())))
+        .collect()
+}
+
+fn rename_tags(archives: &[Archive], from: &[String], to: &str) -> Vec<TagChange> {
+    edit_library(archives, |tags| {
+        tags.into_iter()
+            .map(|tag| {
+                if from.contains(&tag) {
+                    to.to_string()
+                } else {
+                    tag
+                }
+            })
+            .collect()
+    })
+}
+
+fn delete_tags(
+    archives: &[Archive],
+    namespaces: &[String],
+    regex: Option<&Regex>,
+) -> Vec<TagChange> {
+    edit_library(archives, |tags| {
+        tags.into_iter()
+            .filter(|tag| {
+                let namespace = tag.split_once(':').map(|(ns, _)| ns).unwrap_or_default();
+                !namespaces.iter().any(|ns| ns == namespace)
+                    && !regex.is_some_and(|re| re.is_match(tag))
+            })
+            .collect()
+    })
+}
+
+fn dedupe_tags(archives: &[Archive]) -> Vec<TagChange> {
+    edit_library(archives, |tags| {
+        let mut seen = vec![];
+        tags.into_iter()
+            .filter(|tag| {
+                let lower = tag.to_lowercase();
+                if seen.contains(&lower) {
+                    return false;
+                }
+                seen.push(lower);
+                true
+            })
+            .collect()
+    })
+}
+
+pub fn print_batches(journal: &[JournalEntry]) {
+    let mut batches: Vec<(&str, usize)> = vec![];
+    for entry in journal {
+        match batches.iter_mut().find(|(batch, _)| *batch == entry.batch) {
+            Some((_, count)) => *count += 1,
+            None => batches.push((&entry.batch, 1)),
+        }
+    }
+    for (batch, count) in batches {
+        println!("{}  {} 次写入", batch.cyan(), count);
+    }
+}
+
+/// 恢复到该批次第一次写入前的标签；之后被改过的作品跳过
+pub fn undo_changes(archives: &[Archive], journal: &[JournalEntry], batch: &str) -> Vec<TagChange> {
+    let mut restore: HashMap<&str, (&str, &str)> = HashMap::new();
+    for entry in journal.iter().filter(|entry| entry.batch == batch) {
+        restore
+            .entry(&entry.arcid)
+            .and_modify(|(_, new_tags)| *new_tags = &entry.new_tags)
+            .or_insert((&entry.old_tags, &entry.new_tags));
+    }
+    archives
+        .iter()
+        .filter_map(|archive| {
+            let (old_tags, new_tags) = restore.get(archive.arcid.as_str())?;
+            if archive.tag_list() != split_tags(new_tags) {
+                println!(
+                    "{} 在该批次之后被修改过，跳过",
+                    archive.title.bright_yellow()
+                );
+                return None;
+            }
+            TagChange::new(archive, split_tags(old_tags))
+        })
+        .collect()
+}
+
+/// 恢复该作品最近一次写入前的标签
+pub fn revert_archive(
+    archives: &[Archive],
+    journal: &[JournalEntry],
+    arcid: &str,
+) -> Vec<TagChange> {
+    let Some(entry) = journal.iter().rev().find(|entry| entry.arcid == arcid) else {
+        println!("没有 {} 的写入记录", arcid.bright_yellow());
+        return vec![];
+    };
+    archives
+        .iter()
+        .filter(|archive| archive.arcid == arcid)
+        .filter_map(|archive| {
+            if archive.tag_list() != split_tags(&entry.new_tags) {
+                println!(
+                    "{} 在最近一次写入后被修改过，跳过",
+                    archive.title.bright_yellow()
+                );
+                return None;
+            }
+            TagChange::new(archive, split_tags(&entry.old_tags))
+        })
+        .collect()
+}
+
+pub async fn apply(settings: &Settings, changes: Vec<TagChange>, options: &EditOptions) {
+    apply_changes(settings, &changes, options.dry_run, !options.yes).await;
+}
+
+pub async fn tags_command(settings: &Settings, archives: &[Archive], action: &TagsAction) {
+    match action {
+        TagsAction::Rename { from, to, options } => {
+            apply(
+                settings,
+                rename_tags(archives, std::slice::from_ref(from), to),
+                options,
+            )
+            .await
+        }
+        TagsAction::Merge {
+            into,
+            from,
+            options,
+        } => apply(settings, rename_tags(archives, from, into), options).await,
+        TagsAction::Delete {
+            namespace,
+            regex,
+            options,
+        } => {
+            let regex = match regex.as_deref().map(Regex::new).transpose() {
+                Ok(regex) => regex,
+                Err(err) => {
+                    println!("正则错误: {}", err);
+                    return;
+                }
+            };
+            apply(
+                settings,
+                delete_tags(archives, namespace, regex.as_ref()),
+                options,
+            )
+            .await
+        }
+        TagsAction::Dedupe { options } => apply(settings, dedupe_tags(archives), options).await,
+        TagsAction::Undo { batch, options } => {
+            let journal = read_journal();
+            match batch {
+                Some(batch) => {
+                    apply(settings, undo_changes(archives, &journal, batch), options).await
+                }
+                None => print_batches(&journal),
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use super::archive::Archive;
+use super::config::Settings;
+use super::rewrite::{apply_changes, TagChange};
+use super::store::{record_tag_db, store};
+use super::tag::{translate_tag, CnTags, DbHead, TagDictionary};
+
+use owo_colors::OwoColorize;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::PoisonError;
+
+/// 用过的标签数据库版本
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub head: DbHead,
+    pub saved_at: i64,
+    pub tags: HashMap<String, String>,
+}
+
+/// 第一次用到某个版本时保存快照
+pub fn save_snapshot(dict: &TagDictionary) {
+    record_tag_db(&dict.head);
+    if dict.head.sha.is_empty() {
+        return;
+    }
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM tag_db_snapshots WHERE sha = ?1",
+            params![dict.head.sha],
+            |_| Ok(()),
+        )
+        .optional()
+        .unwrap()
+        .is_some();
+    if exists {
+        return;
+    }
+    conn.execute(
+        "INSERT INTO tag_db_snapshots (sha, tags) VALUES (?1, ?2)",
+        params![
+            dict.head.sha,
+            serde_json::to_string(&dict.to_cn_map()).unwrap()
+        ],
+    )
+    .unwrap();
+}
+
+/// 按第一次使用的时间从新到旧
+pub fn load_snapshots() -> Vec<Snapshot> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut stmt = conn
+        .prepare(
+            "SELECT v.sha, v.version, v.message, v.first_seen, s.tags
+             FROM tag_db_snapshots s JOIN tag_db_versions v ON v.sha = s.sha
+             ORDER BY v.first_seen DESC",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| {
+            let tags: String = row.get(4)?;
+            Ok(Snapshot {
+                head: DbHead {
+                    sha: row.get(0)?,
+                    version: row.get(1)?,
+                    message: row.get(2)?,
+                },
+                saved_at: row.get(3)?,
+                tags: serde_json::from_str(&tags).unwrap_or_default(),
+            })
+        })
+        .unwrap();
+    rows.map(Result::unwrap).collect()
+}
+
+/// 旧中文标签 -> 新中文标签；旧名对应多个新名时无法迁移，返回在第二项
+///
+/// `old`/`new` 都是标签数据库本身的翻译，只有数据库改了名称的标签才迁移；
+/// 新名称再经过覆盖词典，被丢弃的标签不迁移
+fn rename_map(
+    old: &HashMap<String, String>,
+    new: &HashMap<String, String>,
+    cn_tags: &CnTags,
+) -> (HashMap<String, String>, Vec<String>) {
+    let mut renames: HashMap<String, String> = HashMap::new();
+    let mut conflicts = vec![];
+    for (raw_tag, old_tag) in old {
+        let Some(new_tag) = new.get(raw_tag) else {
+            continue;
+        };
+        if new_tag.is_empty() || new_tag == old_tag {
+            continue;
+        }
+        let Some(new_tag) = translate_tag(cn_tags, raw_tag) else {
+            continue;
+        };
+        if &new_tag == old_tag {
+            continue;
+        }
+        match renames.get(old_tag) {
+            Some(existing) if *existing != new_tag => conflicts.push(old_tag.clone()),
+            _ => {
+                renames.insert(old_tag.clone(), new_tag);
+            }
+        }
+    }
+    for tag in &conflicts {
+        renames.remove(tag);
+    }
+    conflicts.sort();
+    conflicts.dedup();
+    (renames, conflicts)
+}
+
+/// 把按旧版本数据库写入的中文标签改成最新的名称
+///
+/// `cn_map` 是当前数据库的 `to_cn_map`，与快照对比；`cn_tags` 是合并覆盖词典后的翻译
+pub async fn migrate_tags(
+    settings: &Settings,
+    archives: &[Archive],
+    cn_map: &HashMap<String, String>,
+    cn_tags: &CnTags,
+    current: &DbHead,
+    from: Option<&str>,
+    dry_run: bool,
+) {
+    let snapshots = load_snapshots();
+    let snapshot = match from {
+        Some(sha) => snapshots.iter().find(|s| s.head.sha.starts_with(sha)),
+        None => snapshots.iter().find(|s| s.head.sha != current.sha),
+    };
+    let Some(snapshot) = snapshot else {
+        println!("没有可用于对比的旧版本快照");
+        return;
+    };
+    println!(
+        "从版本 {} ({}) 迁移到 {} ({})",
+        snapshot.head.version.yellow(),
+        snapshot.head.sha.chars().take(7).collect::<String>(),
+        current.version.bright_green(),
+        current.sha.chars().take(7).collect::<String>()
+    );
+
+    let (renames, conflicts) = rename_map(&snapshot.tags, cn_map, cn_tags);
+    let mut sorted: Vec<_> = renames.iter().collect();
+    sorted.sort();
+    for (old_tag, new_tag) in sorted {
+        println!("{} -> {}", old_tag.red(), new_tag.green());
+    }
+    for tag in &conflicts {
+        println!("{} 对应多个新名称，跳过", tag.bright_yellow());
+    }
+
+    let changes: Vec<TagChange> = archives
+        .iter()
+        .filter_map(|archive| {
+            let tags = archive
+                .tag_list()
+                .into_iter()
+                .map(|tag| renames.get(&tag).cloned().unwrap_or(tag))
+                .collect();
+            TagChange::new(archive, tags)
+        })
+        .collect();
+    apply_changes(settings, &changes, dry_run, false).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn renames_follow_db_changes_only() {
+        let old = map(&[
+            ("female:glasses", "女性:眼镜"),
+            ("female:stockings", "女性:长筒袜"),
+            ("other:ads", "其他:广告"),
+        ]);
+        let new = map(&[
+            ("female:glasses", "女性:眼镜"),
+            ("female:stockings", "女性:丝袜"),
+            ("other:ads", "其他:广告页"),
+        ]);
+        let mut cn_tags = CnTags::new(new.clone());
+        // 覆盖词典改了名称但数据库没有变化的标签不迁移
+        cn_tags
+            .tags
+            .insert("female:glasses".to_string(), "女性:眼镜娘".to_string());
+        cn_tags.tags.remove("other:ads");
+        cn_tags.dropped.insert("other:ads".to_string());
+        let (renames, conflicts) = rename_map(&old, &new, &cn_tags);
+        assert_eq!(renames, map(&[("女性:长筒袜", "女性:丝袜")]));
+        assert!(conflicts.is_empty());
+    }
+}
@@ -1,8 +1,32 @@
+pub mod apply;
 pub mod archive;
 pub mod args;
+pub mod calibrate;
+pub mod category;
+pub mod checkpoint;
+pub mod collect;
+pub mod commands;
+pub mod config;
 pub mod eh;
 pub mod error;
+pub mod hash;
+pub mod journal;
+pub mod maintain;
+pub mod migrate;
+pub mod overrides;
+pub mod plugin;
 pub mod progress;
+pub mod rewrite;
+pub mod score;
+pub mod select;
+pub mod store;
 pub mod tag;
+pub mod tank;
+pub mod translate;
+pub mod tui;
+pub mod unattended;
 pub mod unhandle;
+pub mod untagged;
+pub mod untranslate;
+pub mod untranslated;
 pub mod utils;
@@ -0,0 +1,144 @@
+use super::tag::CnTags;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// 本地覆盖词典，优先级高于 EhTagTranslation
+///
+/// ```toml
+/// keep_english = ["other:full color"]
+/// drop = ["other:extraneous ads"]
+///
+/// [tags]
+/// "female:big breasts" = "巨乳"
+///
+/// [namespaces]
+/// female = "女"
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TagOverrides {
+    /// `namespace:tag` -> 自定义名称；不含 `:` 时沿用该 namespace 的中文名
+    pub tags: HashMap<String, String>,
+    /// 保持英文原样的标签
+    pub keep_english: Vec<String>,
+    /// 直接丢弃的标签
+    pub drop: Vec<String>,
+    /// namespace -> 自定义中文 namespace
+    pub namespaces: HashMap<String, String>,
+}
+
+impl TagOverrides {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Ok(serde_json::from_str(&text)?),
+            _ => Ok(toml::from_str(&text)?),
+        }
+    }
+
+    /// 覆盖到 `to_cn_map` 的结果上
+    ///
+    /// `namespaces` 是标签数据库的 namespace 映射；改名的 namespace 和丢弃的标签
+    /// 分别记在 `CnTags` 的 `namespaces`/`dropped` 中，不写入标签表
+    pub fn apply(&self, cn_tags: &mut CnTags, namespaces: &HashMap<String, String>) {
+        for (namespace, cn_namespace) in &self.namespaces {
+            let prefix = format!("{}:", namespace);
+            for (key, value) in cn_tags.tags.iter_mut() {
+                if !key.starts_with(&prefix) {
+                    continue;
+                }
+                if let Some((_, name)) = value.split_once(':') {
+                    *value = format!("{}:{}", cn_namespace, name);
+                }
+            }
+            cn_tags
+                .namespaces
+                .insert(namespace.clone(), cn_namespace.clone());
+        }
+
+        for (key, name) in &self.tags {
+            let value = if name.contains(':') {
+                name.clone()
+            } else {
+                let namespace = key.split_once(':').map(|(ns, _)| ns).unwrap_or_default();
+                let cn_namespace = self
+                    .namespaces
+                    .get(namespace)
+                    .or_else(|| namespaces.get(namespace))
+                    .map(String::as_str)
+                    .unwrap_or(namespace);
+                format!("{}:{}", cn_namespace, name)
+            };
+            cn_tags.tags.insert(key.clone(), value);
+        }
+
+        for key in &self.keep_english {
+            cn_tags.tags.insert(key.clone(), key.clone());
+        }
+
+        for key in &self.drop {
+            cn_tags.tags.remove(key);
+            cn_tags.dropped.insert(key.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn bare_name_uses_namespace_map() {
+        let overrides = TagOverrides {
+            tags: map(&[("female:glasses", "眼镜娘"), ("male:glasses", "男:眼镜")]),
+            ..Default::default()
+        };
+        let mut cn_tags = CnTags::new(map(&[("female:big breasts", "女性:巨乳")]));
+        overrides.apply(&mut cn_tags, &map(&[("female", "女性")]));
+        assert_eq!(cn_tags.tags["female:glasses"], "女性:眼镜娘");
+        assert_eq!(cn_tags.tags["male:glasses"], "男:眼镜");
+    }
+
+    #[test]
+    fn bare_name_follows_renamed_namespace() {
+        let overrides = TagOverrides {
+            tags: map(&[("female:glasses", "眼镜娘")]),
+            namespaces: map(&[("female", "女")]),
+            ..Default::default()
+        };
+        let mut cn_tags = CnTags::new(map(&[("female:big breasts", "女性:巨乳")]));
+        overrides.apply(&mut cn_tags, &map(&[("female", "女性")]));
+        assert_eq!(cn_tags.tags["female:glasses"], "女:眼镜娘");
+        assert_eq!(cn_tags.tags["female:big breasts"], "女:巨乳");
+        assert_eq!(cn_tags.namespaces["female"], "女");
+        // 改名的 namespace 不会混进标签表
+        assert_eq!(cn_tags.tags.len(), 2);
+    }
+
+    #[test]
+    fn keep_and_drop() {
+        let overrides = TagOverrides {
+            keep_english: vec!["other:full color".to_string()],
+            drop: vec!["other:extraneous ads".to_string()],
+            ..Default::default()
+        };
+        let mut cn_tags = CnTags::new(map(&[
+            ("other:full color", "其他:全彩"),
+            ("other:extraneous ads", "其他:多余广告"),
+        ]));
+        overrides.apply(&mut cn_tags, &HashMap::new());
+        assert_eq!(cn_tags.tags["other:full color"], "other:full color");
+        assert!(!cn_tags.tags.contains_key("other:extraneous ads"));
+        assert!(cn_tags.dropped.contains("other:extraneous ads"));
+    }
+}
@@ -0,0 +1,107 @@
+use super::archive::{split_tags, Archive};
+use super::checkpoint::write_lock;
+use super::commands::write_tags;
+use super::config::Settings;
+use super::error::FetchError;
+use super::progress::log;
+use super::store::record_plugin;
+use super::tag::CnTags;
+use super::translate::translate_tags;
+
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+
+/// `/api/plugins/use` 的返回
+#[derive(Debug, Deserialize)]
+struct PluginResponse {
+    #[serde(default)]
+    success: i64,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    data: Option<PluginData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginData {
+    #[serde(default)]
+    new_tags: String,
+}
+
+/// 让 LANraragi 对作品运行一个元数据插件，返回插件找到的标签，不写入
+async fn use_plugin(
+    settings: &Settings,
+    namespace: &str,
+    arcid: &str,
+) -> Result<Vec<String>, FetchError> {
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/api/plugins/use", &settings.endpoint))
+        .query(&[
+            ("plugin", namespace),
+            ("id", arcid),
+            ("key", &settings.api_key),
+        ])
+        .send()
+        .await?;
+    let result = resp.json::<PluginResponse>().await?;
+    if result.success != 1 {
+        return Err(FetchError::Other(
+            result.error.unwrap_or_else(|| "插件运行失败".to_string()),
+        ));
+    }
+    let tags = split_tags(&result.data.map(|d| d.new_tags).unwrap_or_default());
+    if tags.is_empty() {
+        return Err(FetchError::Other("没有找到标签".to_string()));
+    }
+    Ok(tags)
+}
+
+/// 依次尝试配置的插件，把第一个找到新标签的插件的结果翻译后写入，返回该插件
+pub async fn plugin_fallback(
+    settings: &Settings,
+    archive: &Archive,
+    tag_cn: &CnTags,
+) -> Option<String> {
+    for namespace in &settings.plugins {
+        let raw_tags = match use_plugin(settings, namespace, &archive.arcid).await {
+            Ok(raw_tags) => raw_tags,
+            Err(err) => {
+                log(format!(
+                    "插件 {} 未找到: {}",
+                    namespace.cyan(),
+                    err.yellow()
+                ));
+                continue;
+            }
+        };
+        let existing = archive.tag_list();
+        let tags: Vec<String> = translate_tags(raw_tags, tag_cn)
+            .into_iter()
+            .filter(|tag| !existing.contains(tag))
+            .collect();
+        if tags.is_empty() {
+            log(format!("插件 {} 没有新的标签", namespace.cyan()));
+            continue;
+        }
+        let result = {
+            let _guard = write_lock().lock().await;
+            write_tags(settings, archive, &tags.join(",")).await
+        };
+        match result {
+            Ok(()) => {
+                record_plugin(&archive.arcid, namespace);
+                log(format!(
+                    "✅{} 由插件 {} 写入",
+                    archive.title.bright_blue(),
+                    namespace.cyan()
+                ));
+                return Some(namespace.clone());
+            }
+            Err(err) => {
+                log(format!("❌write failed: {}", err.red()));
+                return None;
+            }
+        }
+    }
+    None
+}
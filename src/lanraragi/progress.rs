@@ -5,13 +5,49 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use owo_colors::OwoColorize;
 use reqwest::Response;
 use std::cmp::min;
-use std::sync::OnceLock;
+use std::fmt::Display;
+use std::sync::{Mutex, OnceLock};
 
 pub fn multi_progress() -> &'static MultiProgress {
     static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
     PROGRESS.get_or_init(MultiProgress::new)
 }
 
+/// 全屏界面运行时输出先存在这里，由界面取出显示
+fn captured() -> &'static Mutex<Option<Vec<String>>> {
+    static CAPTURED: OnceLock<Mutex<Option<Vec<String>>>> = OnceLock::new();
+    CAPTURED.get_or_init(|| Mutex::new(None))
+}
+
+/// 开始收集 `log` 的输出而不打印
+pub fn capture_log() {
+    *captured().lock().unwrap() = Some(vec![]);
+}
+
+/// 取出收集到的输出
+pub fn take_log() -> Vec<String> {
+    captured()
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
+}
+
+/// 停止收集，返回还没有取出的输出
+pub fn release_log() -> Vec<String> {
+    captured().lock().unwrap().take().unwrap_or_default()
+}
+
+/// 输出一行；正在显示的进度条先收起再重绘，不会被打乱
+pub fn log(line: impl Display) {
+    if let Some(lines) = captured().lock().unwrap().as_mut() {
+        lines.push(line.to_string());
+        return;
+    }
+    multi_progress().suspend(|| println!("{}", line));
+}
+
 pub async fn make_progress_bar(resp: Response, name: &str) -> Result<Vec<u8>, FetchError> {
     let total = match resp.content_length() {
         Some(total) => total,
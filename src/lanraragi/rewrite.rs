@@ -0,0 +1,77 @@
+use super::archive::Archive;
+use super::config::Settings;
+
+use owo_colors::OwoColorize;
+
+/// 对单个作品的标签改写
+#[derive(Debug, Clone)]
+pub struct TagChange {
+    pub archive: Archive,
+    pub tags: Vec<String>,
+}
+
+impl TagChange {
+    /// 新旧标签不同时才返回改写
+    pub fn new(archive: &Archive, tags: Vec<String>) -> Option<Self> {
+        let mut deduped: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            if !deduped.contains(&tag) {
+                deduped.push(tag);
+            }
+        }
+        if deduped == archive.tag_list() {
+            return None;
+        }
+        Some(Self {
+            archive: archive.clone(),
+            tags: deduped,
+        })
+    }
+
+    pub fn print_diff(&self) {
+        let old = self.archive.tag_list();
+        println!("{}", &self.archive.title.bright_blue());
+        let mut seen = vec![];
+        for tag in &old {
+            // 重复的标签也算删除
+            if !self.tags.contains(tag) || seen.contains(&tag) {
+                println!("  - {}", tag.red());
+            }
+            seen.push(tag);
+        }
+        for tag in self.tags.iter().filter(|tag| !old.contains(tag)) {
+            println!("  + {}", tag.green());
+        }
+    }
+}
+
+fn confirm(count: usize) -> bool {
+    println!("确认写入 {} 个作品? [y/N]", count.bright_yellow());
+    let mut input = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    matches!(input.trim(), "y" | "Y")
+}
+
+/// 先显示全部改动，`ask` 为 true 时写入前需要确认
+pub async fn apply_changes(settings: &Settings, changes: &[TagChange], dry_run: bool, ask: bool) {
+    for change in changes {
+        change.print_diff();
+    }
+    println!("共 {} 个作品受影响", changes.len().bright_green());
+    if dry_run || changes.is_empty() || (ask && !confirm(changes.len())) {
+        return;
+    }
+    for (i, change) in changes.iter().enumerate() {
+        if let Err(err) = change
+            .archive
+            .change_tags_to_lanraragi(settings, &change.tags.join(","))
+            .await
+        {
+            println!("❌{} {}", change.archive.title, err.red());
+            continue;
+        }
+        println!("已处理 {}/{}", (i + 1).green(), changes.len().cyan());
+    }
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// 候选作品与本地作品的匹配特征
+#[derive(Debug, Clone, Copy)]
+pub struct Features {
+    /// 标题相似度，0~1
+    pub similarity: f64,
+    pub page_match: bool,
+}
+
+/// 候选作品的评分方式
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Scoring {
+    pub similarity_weight: f64,
+    pub page_weight: f64,
+    /// 最高分不低于该值时自动选择，不再询问
+    pub auto: Option<f64>,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self {
+            similarity_weight: 0.7,
+            page_weight: 0.3,
+            auto: None,
+        }
+    }
+}
+
+impl Scoring {
+    /// 加权后的评分，0~1
+    pub fn score(&self, features: &Features) -> f64 {
+        let total = self.similarity_weight + self.page_weight;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let page = if features.page_match { 1.0 } else { 0.0 };
+        (self.similarity_weight * features.similarity + self.page_weight * page) / total
+    }
+
+    /// 开启自动选择且最高分达到阈值时返回其下标
+    pub fn auto_select(&self, features: &[Features]) -> Option<usize> {
+        let threshold = self.auto?;
+        let (index, best) = features
+            .iter()
+            .map(|f| self.score(f))
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        (best >= threshold).then_some(index)
+    }
+}
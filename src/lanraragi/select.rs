@@ -0,0 +1,264 @@
+use super::archive::Archive;
+use super::args::{SelectArgs, SortKey};
+use super::category::category_arcids;
+use super::config::Settings;
+use super::unhandle::is_ignored;
+
+use owo_colors::OwoColorize;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs::read_to_string;
+
+fn read_arcid_file(path: &std::path::Path) -> Result<HashSet<String>, String> {
+    let text = read_to_string(path).map_err(|e| format!("无法读取 {}: {}", path.display(), e))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+impl SelectArgs {
+    fn is_default(&self) -> bool {
+        self.title.is_none()
+            && self.extension.is_empty()
+            && self.min_pages.is_none()
+            && self.max_pages.is_none()
+            && !self.new
+            && self.category.is_none()
+            && self.arcid_file.is_none()
+            && self.sort.is_none()
+            && !self.reverse
+            && self.offset == 0
+            && self.limit.is_none()
+    }
+}
+
+/// `--explain` 时打印作品被选中或跳过的原因
+pub fn explain(archive: &Archive, included: bool, reason: &str) {
+    if included {
+        println!("{} {}  {}", "✅".green(), archive.title, reason.green());
+    } else {
+        println!(
+            "{} {}  {}",
+            "➖".dimmed(),
+            archive.title.dimmed(),
+            reason.dimmed()
+        );
+    }
+}
+
+/// 按 `settings.untagged` 取出无标签且没有被忽略的作品
+pub fn untagged_archives(
+    settings: &Settings,
+    archives: Vec<Archive>,
+    verbose: bool,
+) -> Vec<Archive> {
+    archives
+        .into_iter()
+        .filter(|archive| {
+            let (included, reason) = if is_ignored(&archive.arcid) {
+                (false, "已忽略".to_string())
+            } else {
+                settings.untagged.judge(archive)
+            };
+            if verbose {
+                explain(archive, included, &reason);
+            }
+            included
+        })
+        .collect()
+}
+
+/// 解析好的筛选条件
+struct Filters<'a> {
+    args: &'a SelectArgs,
+    title: Option<Regex>,
+    extensions: Vec<String>,
+    listed: Option<HashSet<String>>,
+    category: Option<HashSet<String>>,
+}
+
+impl Filters<'_> {
+    /// 不满足条件时返回原因
+    fn reject(&self, a: &Archive) -> Option<String> {
+        let args = self.args;
+        if self.title.as_ref().is_some_and(|re| !re.is_match(&a.title)) {
+            return Some("标题不匹配".to_string());
+        }
+        if !self.extensions.is_empty() && !self.extensions.contains(&a.extension.to_lowercase()) {
+            return Some(format!("扩展名为 {}", a.extension));
+        }
+        if args.min_pages.is_some_and(|min| a.pagecount < min)
+            || args.max_pages.is_some_and(|max| a.pagecount > max)
+        {
+            return Some(format!("页数为 {}", a.pagecount));
+        }
+        if args.new && a.isnew != "true" {
+            return Some("不是新作品".to_string());
+        }
+        if self
+            .category
+            .as_ref()
+            .is_some_and(|ids| !ids.contains(&a.arcid))
+        {
+            return Some("不在分类中".to_string());
+        }
+        if self
+            .listed
+            .as_ref()
+            .is_some_and(|ids| !ids.contains(&a.arcid))
+        {
+            return Some("不在arcid列表中".to_string());
+        }
+        None
+    }
+}
+
+/// 按条件筛选作品并排序，最后应用 `offset` 和 `limit`
+pub async fn select_archives(
+    settings: &Settings,
+    archives: Vec<Archive>,
+    args: &SelectArgs,
+) -> Result<Vec<Archive>, String> {
+    if args.is_default() {
+        return Ok(archives);
+    }
+    let title = match &args.title {
+        Some(pattern) => {
+            Some(Regex::new(pattern).map_err(|e| format!("无效的标题正则 {}: {}", pattern, e))?)
+        }
+        None => None,
+    };
+    let listed = match &args.arcid_file {
+        Some(path) => Some(read_arcid_file(path)?),
+        None => None,
+    };
+    let category = match &args.category {
+        Some(key) => Some(
+            category_arcids(settings, key)
+                .await
+                .map_err(|e| format!("获取分类失败: {}", e))?,
+        ),
+        None => None,
+    };
+    let filters = Filters {
+        args,
+        title,
+        extensions: args
+            .extension
+            .iter()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .collect(),
+        listed,
+        category,
+    };
+
+    let mut selected: Vec<Archive> = archives
+        .into_iter()
+        .filter(|archive| match filters.reject(archive) {
+            Some(reason) => {
+                if args.explain {
+                    explain(archive, false, &reason);
+                }
+                false
+            }
+            None => true,
+        })
+        .collect();
+    match args.sort {
+        Some(SortKey::Title) => selected.sort_by(|a, b| a.title.cmp(&b.title)),
+        Some(SortKey::Pagecount) => selected.sort_by_key(|a| a.pagecount),
+        Some(SortKey::Lastreadtime) => selected.sort_by_key(|a| Reverse(a.lastreadtime)),
+        Some(SortKey::Newest) => selected.sort_by_key(|a| Reverse(a.date_added())),
+        None => {}
+    }
+    if args.reverse {
+        selected.reverse();
+    }
+    let end = args.offset.saturating_add(args.limit.unwrap_or(usize::MAX));
+    let mut kept = vec![];
+    for (i, archive) in selected.into_iter().enumerate() {
+        if (args.offset..end).contains(&i) {
+            kept.push(archive);
+        } else if args.explain {
+            explain(&archive, false, "超出 offset/limit 范围");
+        }
+    }
+    println!("按条件筛选后剩余 {} 条", kept.len().bright_green());
+    Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(arcid: &str, title: &str, extension: &str, pagecount: i32) -> Archive {
+        Archive {
+            arcid: arcid.to_string(),
+            title: title.to_string(),
+            extension: extension.to_string(),
+            pagecount,
+            isnew: "false".to_string(),
+            ..Archive::default()
+        }
+    }
+
+    #[test]
+    fn reject_reports_first_failed_condition() {
+        let args = SelectArgs {
+            min_pages: Some(10),
+            max_pages: Some(100),
+            ..SelectArgs::default()
+        };
+        let filters = Filters {
+            args: &args,
+            title: Some(Regex::new("(?i)series").unwrap()),
+            extensions: vec!["zip".to_string()],
+            listed: Some(HashSet::from(["a".to_string(), "b".to_string()])),
+            category: None,
+        };
+        assert_eq!(filters.reject(&archive("a", "My Series", "ZIP", 20)), None);
+        assert_eq!(
+            filters.reject(&archive("a", "Other", "zip", 20)).unwrap(),
+            "标题不匹配"
+        );
+        assert_eq!(
+            filters.reject(&archive("a", "Series", "rar", 20)).unwrap(),
+            "扩展名为 rar"
+        );
+        assert_eq!(
+            filters.reject(&archive("a", "Series", "zip", 5)).unwrap(),
+            "页数为 5"
+        );
+        assert_eq!(
+            filters.reject(&archive("a", "Series", "zip", 101)).unwrap(),
+            "页数为 101"
+        );
+        assert_eq!(
+            filters.reject(&archive("c", "Series", "zip", 20)).unwrap(),
+            "不在arcid列表中"
+        );
+
+        let args = SelectArgs {
+            new: true,
+            ..SelectArgs::default()
+        };
+        let filters = Filters {
+            args: &args,
+            title: None,
+            extensions: vec![],
+            listed: None,
+            category: Some(HashSet::from(["a".to_string()])),
+        };
+        assert_eq!(
+            filters.reject(&archive("a", "", "zip", 1)).unwrap(),
+            "不是新作品"
+        );
+        let mut new = archive("b", "", "zip", 1);
+        new.isnew = "true".to_string();
+        assert_eq!(filters.reject(&new).unwrap(), "不在分类中");
+    }
+}
@@ -0,0 +1,576 @@
+use super::archive::Archive;
+use super::eh::GL;
+use super::journal::batch_id;
+use super::score::{Features, Scoring};
+use super::tag::DbHead;
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+static STORE_FILE_NAME: &str = "lantag.db";
+
+static SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS archive_snapshots (
+    id INTEGER PRIMARY KEY,
+    arcid TEXT NOT NULL,
+    title TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    pagecount INTEGER NOT NULL,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS archive_snapshots_arcid ON archive_snapshots (arcid);
+
+CREATE TABLE IF NOT EXISTS searches (
+    id INTEGER PRIMARY KEY,
+    arcid TEXT NOT NULL,
+    query TEXT NOT NULL,
+    result_count INTEGER NOT NULL,
+    error TEXT,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS searches_arcid ON searches (arcid);
+
+CREATE TABLE IF NOT EXISTS candidates (
+    id INTEGER PRIMARY KEY,
+    search_id INTEGER NOT NULL REFERENCES searches (id),
+    position INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    title TEXT NOT NULL,
+    similarity REAL NOT NULL,
+    page_match INTEGER NOT NULL,
+    score REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS candidates_search_id ON candidates (search_id);
+
+CREATE TABLE IF NOT EXISTS decisions (
+    id INTEGER PRIMARY KEY,
+    arcid TEXT NOT NULL,
+    search_id INTEGER REFERENCES searches (id),
+    action TEXT NOT NULL,
+    url TEXT,
+    plugin TEXT,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS decisions_arcid ON decisions (arcid);
+
+CREATE TABLE IF NOT EXISTS writes (
+    id INTEGER PRIMARY KEY,
+    arcid TEXT NOT NULL,
+    batch TEXT NOT NULL,
+    old_tags TEXT NOT NULL,
+    new_tags TEXT NOT NULL,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS writes_arcid ON writes (arcid);
+
+CREATE TABLE IF NOT EXISTS tag_db_versions (
+    sha TEXT PRIMARY KEY,
+    version INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    first_seen INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tag_db_snapshots (
+    sha TEXT PRIMARY KEY,
+    tags TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reviews (
+    arcid TEXT PRIMARY KEY,
+    archive TEXT NOT NULL,
+    query TEXT NOT NULL,
+    search_id INTEGER REFERENCES searches (id),
+    candidates TEXT NOT NULL,
+    status TEXT NOT NULL,
+    url TEXT,
+    time INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS queue (
+    arcid TEXT PRIMARY KEY,
+    archive TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    queries TEXT NOT NULL,
+    message TEXT,
+    time INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS runs (
+    run_id TEXT PRIMARY KEY,
+    started_at INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    total INTEGER NOT NULL,
+    finished INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS run_outcomes (
+    run_id TEXT NOT NULL REFERENCES runs (run_id),
+    arcid TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    PRIMARY KEY (run_id, arcid)
+);
+";
+
+/// 本地状态数据库 lantag.db
+///
+/// 持有锁的任务 panic 时未提交的事务会回滚，所以锁中毒后照常使用，
+/// 不让一个作品出错影响后面的作品
+pub fn store() -> &'static Mutex<Connection> {
+    static STORE: OnceLock<Mutex<Connection>> = OnceLock::new();
+    STORE.get_or_init(|| {
+        let conn = Connection::open(STORE_FILE_NAME).unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        Mutex::new(conn)
+    })
+}
+
+/// 用户对搜索结果的处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// 手动选择候选
+    Selected,
+    /// 评分达到阈值自动选择
+    Auto,
+    /// 跳过
+    Skipped,
+    /// 直接指定画廊URL
+    Manual,
+    /// 写入审核阶段批准的选择
+    Approved,
+    /// 搜索失败后由 LANraragi 插件找到标签
+    Plugin,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Selected => "selected",
+            Decision::Auto => "auto",
+            Decision::Skipped => "skipped",
+            Decision::Manual => "manual",
+            Decision::Approved => "approved",
+            Decision::Plugin => "plugin",
+        }
+    }
+}
+
+/// 标题或标签和上次记录不同时保存作品快照
+pub fn record_archive(archive: &Archive) {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let last: Option<(String, String)> = conn
+        .query_row(
+            "SELECT title, tags FROM archive_snapshots WHERE arcid = ?1 ORDER BY id DESC LIMIT 1",
+            params![archive.arcid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .unwrap();
+    if last.is_some_and(|(title, tags)| title == archive.title && tags == archive.tags) {
+        return;
+    }
+    conn.execute(
+        "INSERT INTO archive_snapshots (arcid, title, tags, pagecount, time) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            archive.arcid,
+            archive.title,
+            archive.tags,
+            archive.pagecount,
+            Utc::now().timestamp()
+        ],
+    )
+    .unwrap();
+}
+
+/// 记录一次搜索，返回搜索编号
+pub fn record_search(arcid: &str, query: &str, result_count: usize, error: Option<&str>) -> i64 {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    conn.execute(
+        "INSERT INTO searches (arcid, query, result_count, error, time) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![arcid, query, result_count, error, Utc::now().timestamp()],
+    )
+    .unwrap();
+    conn.last_insert_rowid()
+}
+
+/// 记录搜索结果中的候选作品及其评分，`candidates` 为 (URL, 标题, 特征)
+pub fn record_candidates(search_id: i64, candidates: &[(&str, &str, Features)], scoring: &Scoring) {
+    let mut conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let tx = conn.transaction().unwrap();
+    for (position, (url, title, features)) in candidates.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO candidates (search_id, position, url, title, similarity, page_match, score)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                search_id,
+                position,
+                url,
+                title,
+                features.similarity,
+                features.page_match,
+                scoring.score(features)
+            ],
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+}
+
+pub fn record_decision(arcid: &str, search_id: Option<i64>, decision: Decision, url: Option<&str>) {
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "INSERT INTO decisions (arcid, search_id, action, url, time) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                arcid,
+                search_id,
+                decision.as_str(),
+                url,
+                Utc::now().timestamp()
+            ],
+        )
+        .unwrap();
+}
+
+/// 记录插件写入的标签来源
+pub fn record_plugin(arcid: &str, plugin: &str) {
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "INSERT INTO decisions (arcid, action, plugin, time) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                arcid,
+                Decision::Plugin.as_str(),
+                plugin,
+                Utc::now().timestamp()
+            ],
+        )
+        .unwrap();
+}
+
+pub fn record_write(archive: &Archive, new_tags: &str) {
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "INSERT INTO writes (arcid, batch, old_tags, new_tags, time) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                archive.arcid,
+                batch_id(),
+                archive.tags,
+                new_tags,
+                Utc::now().timestamp()
+            ],
+        )
+        .unwrap();
+}
+
+pub fn record_tag_db(head: &DbHead) {
+    if head.sha.is_empty() {
+        return;
+    }
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "INSERT OR IGNORE INTO tag_db_versions (sha, version, message, first_seen) VALUES (?1, ?2, ?3, ?4)",
+            params![head.sha, head.version, head.message, Utc::now().timestamp()],
+        )
+        .unwrap();
+}
+
+/// 作品历史中的一条记录
+#[derive(Debug)]
+pub enum HistoryEvent {
+    Snapshot {
+        title: String,
+        tags: String,
+    },
+    Search {
+        query: String,
+        result_count: usize,
+        error: Option<String>,
+        /// (URL, 标题, 评分)
+        candidates: Vec<(String, String, f64)>,
+    },
+    Decision {
+        action: String,
+        url: Option<String>,
+        plugin: Option<String>,
+    },
+    Write {
+        batch: String,
+        old_tags: String,
+        new_tags: String,
+    },
+}
+
+/// 按时间顺序返回对作品做过的所有操作
+pub fn history(arcid: &str) -> Vec<(i64, HistoryEvent)> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    // (时间, 同一秒内的先后, 编号, 记录)
+    let mut events: Vec<(i64, u8, i64, HistoryEvent)> = vec![];
+
+    let mut stmt = conn
+        .prepare("SELECT id, time, title, tags FROM archive_snapshots WHERE arcid = ?1")
+        .unwrap();
+    let rows = stmt
+        .query_map(params![arcid], |row| {
+            Ok((
+                row.get(1)?,
+                0,
+                row.get(0)?,
+                HistoryEvent::Snapshot {
+                    title: row.get(2)?,
+                    tags: row.get(3)?,
+                },
+            ))
+        })
+        .unwrap();
+    events.extend(rows.map(Result::unwrap));
+
+    let mut candidates_stmt = conn
+        .prepare("SELECT url, title, score FROM candidates WHERE search_id = ?1 ORDER BY position")
+        .unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id, time, query, result_count, error FROM searches WHERE arcid = ?1")
+        .unwrap();
+    let rows = stmt
+        .query_map(params![arcid], |row| {
+            let id: i64 = row.get(0)?;
+            let candidates = candidates_stmt
+                .query_map(params![id], |c| Ok((c.get(0)?, c.get(1)?, c.get(2)?)))?
+                .collect::<Result<_, _>>()?;
+            Ok((
+                row.get(1)?,
+                1,
+                id,
+                HistoryEvent::Search {
+                    query: row.get(2)?,
+                    result_count: row.get(3)?,
+                    error: row.get(4)?,
+                    candidates,
+                },
+            ))
+        })
+        .unwrap();
+    events.extend(rows.map(Result::unwrap));
+
+    let mut stmt = conn
+        .prepare("SELECT id, time, action, url, plugin FROM decisions WHERE arcid = ?1")
+        .unwrap();
+    let rows = stmt
+        .query_map(params![arcid], |row| {
+            Ok((
+                row.get(1)?,
+                2,
+                row.get(0)?,
+                HistoryEvent::Decision {
+                    action: row.get(2)?,
+                    url: row.get(3)?,
+                    plugin: row.get(4)?,
+                },
+            ))
+        })
+        .unwrap();
+    events.extend(rows.map(Result::unwrap));
+
+    let mut stmt = conn
+        .prepare("SELECT id, time, batch, old_tags, new_tags FROM writes WHERE arcid = ?1")
+        .unwrap();
+    let rows = stmt
+        .query_map(params![arcid], |row| {
+            Ok((
+                row.get(1)?,
+                3,
+                row.get(0)?,
+                HistoryEvent::Write {
+                    batch: row.get(2)?,
+                    old_tags: row.get(3)?,
+                    new_tags: row.get(4)?,
+                },
+            ))
+        })
+        .unwrap();
+    events.extend(rows.map(Result::unwrap));
+
+    events.sort_by_key(|(time, rank, id, _)| (*time, *rank, *id));
+    events
+        .into_iter()
+        .map(|(time, _, _, event)| (time, event))
+        .collect()
+}
+
+/// 一次手动选择或跳过，作为评分的训练样本
+#[derive(Debug)]
+pub struct LabeledExample {
+    pub features: Vec<Features>,
+    /// 选中的候选下标，跳过时为空
+    pub chosen: Option<usize>,
+}
+
+/// 所有带候选的手动选择和跳过记录，自动选择的不算
+pub fn labeled_examples() -> Vec<LabeledExample> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut candidates_stmt = conn
+        .prepare(
+            "SELECT url, similarity, page_match FROM candidates WHERE search_id = ?1 ORDER BY position",
+        )
+        .unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT search_id, url FROM decisions
+             WHERE search_id IS NOT NULL AND action IN ('selected', 'skipped')",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| {
+            let search_id: i64 = row.get(0)?;
+            let url: Option<String> = row.get(1)?;
+            let candidates: Vec<(String, Features)> = candidates_stmt
+                .query_map(params![search_id], |c| {
+                    Ok((
+                        c.get(0)?,
+                        Features {
+                            similarity: c.get(1)?,
+                            page_match: c.get(2)?,
+                        },
+                    ))
+                })?
+                .collect::<Result<_, _>>()?;
+            let chosen = url.and_then(|url| candidates.iter().position(|(u, _)| *u == url));
+            Ok(LabeledExample {
+                features: candidates.into_iter().map(|(_, f)| f).collect(),
+                chosen,
+            })
+        })
+        .unwrap();
+    rows.map(Result::unwrap)
+        .filter(|example| !example.features.is_empty())
+        .collect()
+}
+
+/// 收集阶段保存的待审核作品的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Skipped,
+    Applied,
+}
+
+impl ReviewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Skipped => "skipped",
+            ReviewStatus::Applied => "applied",
+        }
+    }
+}
+
+/// 收集阶段保存的搜索结果，审核时不需要联网
+#[derive(Debug)]
+pub struct Review {
+    pub archive: Archive,
+    pub query: String,
+    pub search_id: Option<i64>,
+    pub gls: Vec<GL>,
+}
+
+pub fn save_review(review: &Review, status: ReviewStatus, url: Option<&str>) {
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "INSERT OR REPLACE INTO reviews (arcid, archive, query, search_id, candidates, status, url, time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                review.archive.arcid,
+                serde_json::to_string(&review.archive).unwrap(),
+                review.query,
+                review.search_id,
+                serde_json::to_string(&review.gls).unwrap(),
+                status.as_str(),
+                url,
+                Utc::now().timestamp()
+            ],
+        )
+        .unwrap();
+}
+
+pub fn set_review_status(arcid: &str, status: ReviewStatus, url: Option<&str>) {
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "UPDATE reviews SET status = ?2, url = ?3, time = ?4 WHERE arcid = ?1",
+            params![arcid, status.as_str(), url, Utc::now().timestamp()],
+        )
+        .unwrap();
+}
+
+/// 作品写入成功后，已批准的审核记录标记为已写入
+pub fn mark_review_applied(arcid: &str) {
+    store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "UPDATE reviews SET status = ?2, time = ?3 WHERE arcid = ?1 AND status = 'approved'",
+            params![
+                arcid,
+                ReviewStatus::Applied.as_str(),
+                Utc::now().timestamp()
+            ],
+        )
+        .unwrap();
+}
+
+pub fn pending_reviews() -> Vec<Review> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut stmt = conn
+        .prepare(
+            "SELECT archive, query, search_id, candidates FROM reviews
+             WHERE status = 'pending' ORDER BY time",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| {
+            let archive: String = row.get(0)?;
+            let candidates: String = row.get(3)?;
+            Ok(Review {
+                archive: serde_json::from_str(&archive).unwrap(),
+                query: row.get(1)?,
+                search_id: row.get(2)?,
+                gls: serde_json::from_str(&candidates).unwrap_or_default(),
+            })
+        })
+        .unwrap();
+    rows.map(Result::unwrap).collect()
+}
+
+/// 已批准等待写入的 (arcid, 画廊URL)
+pub fn approved_reviews() -> Vec<(String, String)> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut stmt = conn
+        .prepare("SELECT arcid, url FROM reviews WHERE status = 'approved' AND url IS NOT NULL ORDER BY time")
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    rows.map(Result::unwrap).collect()
+}
+
+/// 等待审核或写入的作品，再次收集时跳过
+pub fn collected_arcids() -> HashSet<String> {
+    let conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut stmt = conn
+        .prepare("SELECT arcid FROM reviews WHERE status IN ('pending', 'approved')")
+        .unwrap();
+    let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+    rows.map(Result::unwrap).collect()
+}
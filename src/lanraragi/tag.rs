@@ -1,68 +1,338 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use super::error::FetchError;
 use super::progress::make_progress_bar;
 use super::utils::fetch_raw_with_retry;
 
-async fn fetch() -> Result<serde_json::Value, FetchError> {
-    let resp = fetch_raw_with_retry(|| {
-        reqwest::Client::new().get(
-            "https://github.com/EhTagTranslation/Database/releases/latest/download/db.text.json",
+/// EhTagTranslation 发布的数据库格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbFormat {
+    /// 纯文本
+    Text,
+    /// Markdown 原文
+    Raw,
+    /// HTML
+    Html,
+    /// 包含 raw/text/html/ast 的完整格式
+    Full,
+}
+
+impl DbFormat {
+    fn file_name(&self) -> &'static str {
+        match self {
+            DbFormat::Text => "db.text.json",
+            DbFormat::Raw => "db.raw.json",
+            DbFormat::Html => "db.html.json",
+            DbFormat::Full => "db.full.json",
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!(
+            "https://github.com/EhTagTranslation/Database/releases/latest/download/{}",
+            self.file_name()
         )
-    })
-    .await?;
+    }
+
+    // full 格式的每个字段是 {raw, text, html, ast}，取其中的纯文本
+    fn field(&self, value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Object(obj) => obj
+                .get("text")
+                .or_else(|| obj.get("raw"))
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // 写进 LRR 的名称和显示的说明只能是纯文本，html/raw 需要去掉标记
+    fn plain(&self, value: &serde_json::Value) -> String {
+        let text = self.field(value);
+        match self {
+            DbFormat::Html => strip_html(&text),
+            DbFormat::Raw => strip_markdown(&text),
+            DbFormat::Text | DbFormat::Full => text,
+        }
+    }
+}
+
+fn regex_html_tag() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"<[^>]*>").unwrap())
+}
+
+fn regex_md_image() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"!\[[^\]]*\]\([^)]*\)").unwrap())
+}
+
+fn regex_md_link() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap())
+}
+
+fn strip_html(text: &str) -> String {
+    regex_html_tag()
+        .replace_all(text, "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+fn strip_markdown(text: &str) -> String {
+    let text = regex_md_image().replace_all(text, "");
+    let text = regex_md_link().replace_all(&text, "$1");
+    text.replace("**", "")
+        .replace("~~", "")
+        .replace(['*', '`'], "")
+        .trim()
+        .to_string()
+}
+
+/// 数据库对应的提交信息
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DbHead {
+    pub sha: String,
+    pub message: String,
+    pub version: i64,
+}
+
+/// 单个标签的翻译条目
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TagEntry {
+    pub name: String,
+    pub intro: String,
+    pub links: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TagDictionary {
+    pub head: DbHead,
+    /// namespace -> 中文 namespace
+    pub namespaces: HashMap<String, String>,
+    /// `namespace:tag` -> 条目
+    pub tags: HashMap<String, TagEntry>,
+}
+
+async fn fetch(format: DbFormat) -> Result<serde_json::Value, FetchError> {
+    let url = format.url();
+    let resp = fetch_raw_with_retry(|| reqwest::Client::new().get(&url)).await?;
     Ok(serde_json::from_slice::<serde_json::Value>(
         &make_progress_bar(resp, "cn tag").await?,
     )?)
 }
 
-pub fn parse_data(
-    data: &serde_json::Value,
-) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
-    let data_array = data["data"].as_array().ok_or("invalid data")?;
+impl TagDictionary {
+    pub fn parse(
+        data: &serde_json::Value,
+        format: DbFormat,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let data_array = data["data"].as_array().ok_or("invalid data")?;
 
-    let mut tags: HashMap<String, String> = HashMap::new();
+        let head = DbHead {
+            sha: data["head"]["sha"].as_str().unwrap_or_default().to_string(),
+            message: data["head"]["message"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            version: data["version"].as_i64().unwrap_or_default(),
+        };
 
-    for item in data_array {
-        let namespace = item["namespace"].as_str().unwrap_or_default();
-        let front_matters = item["frontMatters"]["name"].as_str().unwrap_or_default();
+        let mut namespaces = HashMap::new();
+        let mut tags = HashMap::new();
 
-        let namespace = match namespace {
-            "reclass" => "category",
-            _ => namespace,
-        };
+        for item in data_array {
+            let namespace = item["namespace"].as_str().unwrap_or_default();
+            let front_matters = format.plain(&item["frontMatters"]["name"]);
 
-        let mut tag_name: &str;
-        let mut tag_cn_name: &str;
-
-        if let Some(name) = item["data"].as_object() {
-            for (key, value) in name {
-                tag_name = match key.as_str() {
-                    "artistacg" => "artist cg",
-                    "gamecg" => "game cg",
-                    "imageset" => "image set",
-                    _ => key.as_str(),
-                };
-
-                if let Some(tag_data) = value.as_object() {
-                    tag_cn_name = tag_data["name"].as_str().unwrap_or_default();
-                    let tag = format!("{}:{}", front_matters, tag_cn_name);
-                    tags.insert(format!("{}:{}", namespace, tag_name), tag);
+            let namespace = match namespace {
+                "reclass" => "category",
+                _ => namespace,
+            };
+            namespaces.insert(namespace.to_string(), front_matters);
+
+            if let Some(name) = item["data"].as_object() {
+                for (key, value) in name {
+                    let tag_name = match key.as_str() {
+                        "artistacg" => "artist cg",
+                        "gamecg" => "game cg",
+                        "imageset" => "image set",
+                        _ => key.as_str(),
+                    };
+
+                    if value.is_object() {
+                        tags.insert(
+                            format!("{}:{}", namespace, tag_name),
+                            TagEntry {
+                                name: format.plain(&value["name"]),
+                                intro: format.plain(&value["intro"]),
+                                links: format.plain(&value["links"]),
+                            },
+                        );
+                    }
                 }
             }
         }
+
+        Ok(Self {
+            head,
+            namespaces,
+            tags,
+        })
+    }
+
+    /// `namespace:tag` -> `中文namespace:中文tag`
+    pub fn to_cn_map(&self) -> HashMap<String, String> {
+        self.tags
+            .iter()
+            .map(|(key, entry)| {
+                let namespace = key.split_once(':').map(|(ns, _)| ns).unwrap_or_default();
+                let cn_namespace = self
+                    .namespaces
+                    .get(namespace)
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                (key.clone(), format!("{}:{}", cn_namespace, entry.name))
+            })
+            .collect()
     }
 
-    Ok(tags)
+    /// 标签的中文说明，没有说明时返回 None
+    pub fn describe(&self, raw_tag: &str) -> Option<String> {
+        let entry = self.tags.get(raw_tag)?;
+        if entry.intro.is_empty() {
+            return None;
+        }
+        let mut text = format!("{}：{}", entry.name, entry.intro.replace('\n', " "));
+        if !entry.links.is_empty() {
+            text.push_str(&format!(" ({})", entry.links.replace('\n', " ")));
+        }
+        Some(text)
+    }
 }
 
-// 异步函数，获取最新的cn标签
-pub async fn fetch_latest_cn_tag() -> serde_json::Value {
-    match fetch().await {
-        Ok(data) => data,
-        Err(e) => {
-            eprintln!("fetch latest cn tag failed: {}", e);
-            std::process::exit(1);
+/// 生效的翻译：标签数据库的 `to_cn_map` 合并覆盖词典
+#[derive(Debug, Clone, Default)]
+pub struct CnTags {
+    /// `namespace:tag` -> `中文namespace:中文tag`
+    pub tags: HashMap<String, String>,
+    /// 覆盖词典改名的 namespace，没有翻译的标签也使用新名称
+    pub namespaces: HashMap<String, String>,
+    /// 覆盖词典丢弃的标签
+    pub dropped: HashSet<String>,
+}
+
+impl CnTags {
+    pub fn new(tags: HashMap<String, String>) -> Self {
+        Self {
+            tags,
+            ..Default::default()
         }
     }
+
+    /// 有翻译或者被丢弃，不算作未翻译标签
+    pub fn knows(&self, raw_tag: &str) -> bool {
+        self.tags.contains_key(raw_tag) || self.dropped.contains(raw_tag)
+    }
+}
+
+/// 翻译单个 `namespace:tag`，没有翻译时原样返回，被覆盖词典丢弃时返回 None
+///
+/// 没有翻译但覆盖词典改了 namespace 时，只替换 namespace
+pub fn translate_tag(cn_tags: &CnTags, raw_tag: &str) -> Option<String> {
+    if cn_tags.dropped.contains(raw_tag) {
+        return None;
+    }
+    if let Some(cn_tag) = cn_tags.tags.get(raw_tag) {
+        return Some(cn_tag.clone());
+    }
+    let renamed = raw_tag.split_once(':').and_then(|(namespace, name)| {
+        let cn_namespace = cn_tags.namespaces.get(namespace)?;
+        Some(format!("{}:{}", cn_namespace, name))
+    });
+    Some(renamed.unwrap_or_else(|| raw_tag.to_string()))
+}
+
+// 异步函数，获取最新的cn标签
+pub async fn fetch_latest_cn_tag(format: DbFormat) -> Result<serde_json::Value, FetchError> {
+    fetch(format).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db(name: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "head": {"sha": "abc", "message": "m"},
+            "version": 6,
+            "data": [{
+                "namespace": "female",
+                "frontMatters": {"name": name.clone()},
+                "data": {"glasses": {"name": name.clone(), "intro": name, "links": ""}},
+            }],
+        })
+    }
+
+    #[test]
+    fn html_names_are_plain_text() {
+        let data = db(serde_json::json!("<p>眼镜 &amp; <img src=\"x\">框</p>"));
+        let dict = TagDictionary::parse(&data, DbFormat::Html).unwrap();
+        assert_eq!(dict.tags["female:glasses"].name, "眼镜 & 框");
+        assert_eq!(dict.namespaces["female"], "眼镜 & 框");
+        assert_eq!(dict.tags["female:glasses"].intro, "眼镜 & 框");
+    }
+
+    #[test]
+    fn raw_names_are_plain_text() {
+        let data = db(serde_json::json!("![图](# \"u\")**眼镜** [框](https://x)"));
+        let dict = TagDictionary::parse(&data, DbFormat::Raw).unwrap();
+        assert_eq!(dict.tags["female:glasses"].name, "眼镜 框");
+        assert_eq!(dict.tags["female:glasses"].intro, "眼镜 框");
+    }
+
+    #[test]
+    fn full_names_use_text_field() {
+        let data =
+            db(serde_json::json!({"raw": "**眼镜**", "text": "眼镜", "html": "<b>眼镜</b>"}));
+        let dict = TagDictionary::parse(&data, DbFormat::Full).unwrap();
+        assert_eq!(dict.to_cn_map()["female:glasses"], "眼镜:眼镜");
+    }
+
+    #[test]
+    fn untranslated_tag_takes_renamed_namespace() {
+        let cn_tags = CnTags {
+            tags: HashMap::from([("female:glasses".to_string(), "女:眼镜".to_string())]),
+            namespaces: HashMap::from([("female".to_string(), "女".to_string())]),
+            dropped: HashSet::from(["other:ads".to_string()]),
+        };
+        assert_eq!(
+            translate_tag(&cn_tags, "female:glasses").unwrap(),
+            "女:眼镜"
+        );
+        assert_eq!(
+            translate_tag(&cn_tags, "female:unknown").unwrap(),
+            "女:unknown"
+        );
+        assert_eq!(
+            translate_tag(&cn_tags, "male:unknown").unwrap(),
+            "male:unknown"
+        );
+        assert_eq!(translate_tag(&cn_tags, "other:ads"), None);
+        assert!(cn_tags.knows("other:ads"));
+        assert!(!cn_tags.knows("female:unknown"));
+    }
 }
@@ -0,0 +1,609 @@
+use super::archive::Archive;
+use super::args::EditOptions;
+use super::config::Settings;
+use super::eh::GL;
+use super::error::FetchError;
+use super::utils::fetch_raw_with_retry;
+
+use owo_colors::OwoColorize;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::sync::OnceLock;
+use tabled::{builder::Builder, settings::Style};
+
+/// 判断是否同一作者或同一原作的namespace，包括翻译后的中文namespace
+const CREATOR_NAMESPACES: [&str; 4] = ["artist", "parody", "艺术家", "原作"];
+
+/// LANraragi 的合集(Tankoubon)
+#[derive(Debug, Deserialize)]
+struct Tankoubon {
+    id: String,
+    name: String,
+    #[serde(default)]
+    archives: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TankoubonPage {
+    result: Vec<Tankoubon>,
+    total: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Created {
+    tankoubon_id: String,
+}
+
+/// 从标题中识别出的系列名和卷序
+#[derive(Debug, Clone)]
+struct Volume {
+    /// 去掉卷号后的标题，用于显示和命名
+    series: String,
+    /// 比较用的系列名
+    key: String,
+    order: u32,
+}
+
+fn regex_brackets() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"\[[^\]]*\]|【[^】]*】|\([^)]*\)|（[^）]*）").unwrap())
+}
+
+/// 卷号的写法，按顺序尝试
+fn regex_volumes() -> &'static [Regex] {
+    static REGEX: OnceLock<Vec<Regex>> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        [
+            r"(?i)\bvol(?:ume)?\.?\s*(\d+)",
+            r"第\s*([0-9一二三四五六七八九十]+)\s*[巻卷話话集部]",
+            r"([前中後后上下])\s*[編编篇巻卷]",
+            r"#\s*(\d+)",
+            r"\s(\d{1,2})\s*$",
+        ]
+        .iter()
+        .map(|pattern| Regex::new(pattern).unwrap())
+        .collect()
+    })
+}
+
+/// 解析 1~99 的中文数字
+fn parse_cn_number(text: &str) -> Option<u32> {
+    let digit = |c: char| {
+        "一二三四五六七八九"
+            .chars()
+            .position(|d| d == c)
+            .map(|i| i as u32 + 1)
+    };
+    let chars: Vec<char> = text.chars().collect();
+    match chars.as_slice() {
+        ['十'] => Some(10),
+        ['十', ones] => Some(10 + digit(*ones)?),
+        [tens, '十'] => Some(digit(*tens)? * 10),
+        [tens, '十', ones] => Some(digit(*tens)? * 10 + digit(*ones)?),
+        [ones] => digit(*ones),
+        _ => None,
+    }
+}
+
+fn volume_order(text: &str) -> Option<u32> {
+    match text {
+        "前" | "上" => Some(1),
+        "中" => Some(2),
+        "後" | "后" | "下" => Some(3),
+        _ => text.parse().ok().or_else(|| parse_cn_number(text)),
+    }
+}
+
+/// 从标题识别系列名和卷序，没有卷号时返回 None
+fn parse_volume(title: &str) -> Option<Volume> {
+    let stripped = regex_brackets().replace_all(title, " ");
+    let stripped = stripped.trim();
+    regex_volumes().iter().find_map(|regex| {
+        let captures = regex.captures(stripped)?;
+        let order = volume_order(captures.get(1)?.as_str())?;
+        let series = stripped[..captures.get(0)?.start()]
+            .trim_end_matches(|c: char| c.is_whitespace() || "-~～:：、,".contains(c))
+            .to_string();
+        if series.is_empty() {
+            return None;
+        }
+        let key = series
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        Some(Volume { series, key, order })
+    })
+}
+
+fn creator_tags(archive: &Archive) -> HashSet<String> {
+    archive
+        .tag_list()
+        .into_iter()
+        .filter(|tag| {
+            tag.split_once(':')
+                .is_some_and(|(ns, _)| CREATOR_NAMESPACES.contains(&ns))
+        })
+        .map(|tag| tag.to_lowercase())
+        .collect()
+}
+
+fn gallery_id(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("/g/")?;
+    rest.split('/').next().filter(|id| !id.is_empty())
+}
+
+fn source_url(archive: &Archive) -> Option<String> {
+    archive
+        .tag_list()
+        .into_iter()
+        .find_map(|tag| Some(format!("https://{}", tag.strip_prefix("source:")?)))
+}
+
+/// 简单的并查集，同时记录每组的成员
+struct Groups {
+    parent: Vec<usize>,
+    members: Vec<Vec<usize>>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            members: (0..len).map(|i| vec![i]).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        if self.parent[i] != i {
+            let root = self.find(self.parent[i]);
+            self.parent[i] = root;
+        }
+        self.parent[i]
+    }
+
+    /// `i` 所在组的所有成员
+    fn members(&mut self, i: usize) -> &[usize] {
+        let root = self.find(i);
+        &self.members[root]
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[b] = a;
+            let moved = std::mem::take(&mut self.members[b]);
+            self.members[a].extend(moved);
+        }
+    }
+}
+
+/// 两边都有作者/原作标签时需要有相同的
+fn creators_compatible(a: &HashSet<String>, b: &HashSet<String>) -> bool {
+    a.is_empty() || b.is_empty() || !a.is_disjoint(b)
+}
+
+/// 连接标题系列名相同的作品；合并前检查两组的每对成员，
+/// 避免 A、C 作者不同却经由没有标签的 B 连成一组
+fn group_series(
+    volumes: &[Option<Volume>],
+    creators: &[HashSet<String>],
+    groups: &mut Groups,
+    reasons: &mut HashMap<usize, BTreeSet<&'static str>>,
+) {
+    let mut by_series: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, volume) in volumes.iter().enumerate() {
+        if let Some(volume) = volume {
+            by_series.entry(&volume.key).or_default().push(i);
+        }
+    }
+    for members in by_series.values() {
+        for (n, &a) in members.iter().enumerate() {
+            for &b in &members[n + 1..] {
+                if groups.find(a) == groups.find(b) {
+                    continue;
+                }
+                let others = groups.members(b).to_vec();
+                let compatible = groups.members(a).iter().all(|&x| {
+                    others
+                        .iter()
+                        .all(|&y| creators_compatible(&creators[x], &creators[y]))
+                });
+                if !compatible {
+                    continue;
+                }
+                groups.union(a, b);
+                let reason = if creators[a].is_disjoint(&creators[b]) {
+                    "标题卷号"
+                } else {
+                    "标题卷号+作者/原作"
+                };
+                reasons.entry(a).or_default().insert(reason);
+                reasons.entry(b).or_default().insert(reason);
+            }
+        }
+    }
+}
+
+/// 建议组成合集的一组作品
+struct Proposal {
+    name: String,
+    /// 按卷序排列
+    members: Vec<(Archive, Option<u32>)>,
+    /// 分组的依据
+    reasons: BTreeSet<&'static str>,
+    /// 已经包含部分作品的合集
+    existing: Option<(String, Vec<String>)>,
+}
+
+impl Proposal {
+    fn missing(&self) -> Vec<String> {
+        let existing = self.existing.as_ref().map(|(_, ids)| ids.as_slice());
+        self.members
+            .iter()
+            .map(|(archive, _)| archive.arcid.clone())
+            .filter(|arcid| !existing.is_some_and(|ids| ids.contains(arcid)))
+            .collect()
+    }
+
+    /// 写入后合集的完整顺序：本组作品按卷序在前，合集中原有的其他作品保持原顺序排在后面
+    fn ordered(&self) -> Vec<String> {
+        let mut arcids: Vec<String> = self
+            .members
+            .iter()
+            .map(|(archive, _)| archive.arcid.clone())
+            .collect();
+        if let Some((_, existing)) = &self.existing {
+            let others: Vec<String> = existing
+                .iter()
+                .filter(|arcid| !arcids.contains(arcid))
+                .cloned()
+                .collect();
+            arcids.extend(others);
+        }
+        arcids
+    }
+}
+
+/// 读取 EH 画廊页中的父画廊和更新版本，连接库中对应的作品
+async fn link_related(
+    settings: &Settings,
+    archives: &[Archive],
+    groups: &mut Groups,
+    reasons: &mut HashMap<usize, BTreeSet<&'static str>>,
+) {
+    let by_gid: HashMap<String, usize> = archives
+        .iter()
+        .enumerate()
+        .filter_map(|(i, archive)| Some((gallery_id(&source_url(archive)?)?.to_string(), i)))
+        .collect();
+    println!("读取 {} 个画廊页的父画廊信息...", by_gid.len().cyan());
+    for (i, archive) in archives.iter().enumerate() {
+        let Some(gl) = source_url(archive).and_then(|url| GL::from_url(&url)) else {
+            continue;
+        };
+        let page = match gl.fetch_gallery(settings).await {
+            Ok(page) => page,
+            Err(err) => {
+                println!("⚠️{} {}", archive.title, err.yellow());
+                continue;
+            }
+        };
+        for url in &page.related {
+            if let Some(&j) = gallery_id(url).and_then(|gid| by_gid.get(gid)) {
+                groups.union(i, j);
+                reasons.entry(i).or_default().insert("EH父画廊");
+                reasons.entry(j).or_default().insert("EH父画廊");
+            }
+        }
+    }
+}
+
+async fn fetch_tankoubons(settings: &Settings) -> Result<Vec<Tankoubon>, FetchError> {
+    let mut tanks = vec![];
+    for page in 0.. {
+        let resp = fetch_raw_with_retry(|| {
+            reqwest::Client::new()
+                .get(format!("http://{}/api/tankoubons", &settings.endpoint))
+                .query(&[
+                    ("page", page.to_string()),
+                    ("key", settings.api_key.clone()),
+                ])
+        })
+        .await?;
+        let result = resp.json::<TankoubonPage>().await?;
+        let done = result.result.is_empty();
+        tanks.extend(result.result);
+        if done || tanks.len() >= result.total {
+            break;
+        }
+    }
+    Ok(tanks)
+}
+
+/// 按标题卷号、作者/原作标签和 EH 父画廊找出同一系列的作品
+async fn propose(settings: &Settings, archives: &[Archive], parents: bool) -> Vec<Proposal> {
+    let volumes: Vec<Option<Volume>> = archives.iter().map(|a| parse_volume(&a.title)).collect();
+    let creators: Vec<HashSet<String>> = archives.iter().map(creator_tags).collect();
+    let mut groups = Groups::new(archives.len());
+    let mut reasons: HashMap<usize, BTreeSet<&'static str>> = HashMap::new();
+
+    group_series(&volumes, &creators, &mut groups, &mut reasons);
+    if parents {
+        link_related(settings, archives, &mut groups, &mut reasons).await;
+    }
+
+    let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..archives.len() {
+        let root = groups.find(i);
+        components.entry(root).or_default().push(i);
+    }
+    let mut proposals: Vec<Proposal> = components
+        .into_values()
+        .filter(|members| members.len() >= 2)
+        .map(|mut members| {
+            members.sort_by_key(|&i| {
+                (
+                    volumes[i].as_ref().map(|v| v.order),
+                    archives[i].title.clone(),
+                )
+            });
+            let name = members
+                .iter()
+                .find_map(|&i| volumes[i].as_ref().map(|v| v.series.clone()))
+                .unwrap_or_else(|| archives[members[0]].title.clone());
+            Proposal {
+                name,
+                reasons: members
+                    .iter()
+                    .flat_map(|i| reasons.get(i).cloned().unwrap_or_default())
+                    .collect(),
+                members: members
+                    .iter()
+                    .map(|&i| (archives[i].clone(), volumes[i].as_ref().map(|v| v.order)))
+                    .collect(),
+                existing: None,
+            }
+        })
+        .collect();
+    proposals.sort_by(|a, b| a.name.cmp(&b.name));
+    proposals
+}
+
+fn print_proposals(proposals: &[Proposal]) {
+    let mut builder = Builder::default();
+    builder.push_record(["序号", "合集", "卷", "标题", "arcid", "依据"]);
+    for (i, proposal) in proposals.iter().enumerate() {
+        let action = match &proposal.existing {
+            Some((id, _)) => format!("{} (加入 {})", proposal.name, id),
+            None => format!("{} (新建)", proposal.name),
+        };
+        for (n, (archive, order)) in proposal.members.iter().enumerate() {
+            let first = n == 0;
+            builder.push_record([
+                if first {
+                    (i + 1).to_string()
+                } else {
+                    String::new()
+                },
+                if first { action.clone() } else { String::new() },
+                order.map(|o| o.to_string()).unwrap_or_default(),
+                archive.title.clone(),
+                archive.arcid.clone(),
+                if first {
+                    proposal
+                        .reasons
+                        .iter()
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                } else {
+                    String::new()
+                },
+            ]);
+        }
+    }
+    let mut table = builder.build();
+    table.with(Style::rounded());
+    println!("{}", table);
+}
+
+/// 确认要写入哪些合集，`y` 为全部，也可以输入序号如 `1,3`
+fn confirm(len: usize) -> Vec<usize> {
+    print!("写入这些合集? [y/N/序号如 1,3]: ");
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read line");
+    match input.trim() {
+        "y" | "Y" => (0..len).collect(),
+        input => input
+            .split(',')
+            .filter_map(|n| n.trim().parse::<usize>().ok())
+            .filter(|n| (1..=len).contains(n))
+            .map(|n| n - 1)
+            .collect(),
+    }
+}
+
+async fn create_tankoubon(settings: &Settings, name: &str) -> Result<String, FetchError> {
+    let resp = reqwest::Client::new()
+        .put(format!("http://{}/api/tankoubons", &settings.endpoint))
+        .query(&[("name", name), ("key", &settings.api_key)])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(resp.json::<Created>().await?.tankoubon_id)
+}
+
+/// 用完整的作品列表更新合集，列表的顺序就是合集中的顺序
+async fn update_tankoubon(
+    settings: &Settings,
+    id: &str,
+    arcids: &[String],
+) -> Result<(), FetchError> {
+    reqwest::Client::new()
+        .put(format!(
+            "http://{}/api/tankoubons/{}",
+            &settings.endpoint, id
+        ))
+        .query(&[("key", &settings.api_key)])
+        .json(&serde_json::json!({ "archives": arcids }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn write_proposal(settings: &Settings, proposal: &Proposal) -> Result<String, FetchError> {
+    let id = match &proposal.existing {
+        Some((id, _)) => id.clone(),
+        None => create_tankoubon(settings, &proposal.name).await?,
+    };
+    update_tankoubon(settings, &id, &proposal.ordered()).await?;
+    Ok(id)
+}
+
+/// 找出同一系列的作品并建议组成合集，确认后创建或更新 LANraragi 的合集
+pub async fn tank(
+    settings: &Settings,
+    archives: &[Archive],
+    parents: bool,
+    options: &EditOptions,
+) -> Result<(), String> {
+    let mut proposals = propose(settings, archives, parents).await;
+    let tanks = fetch_tankoubons(settings)
+        .await
+        .map_err(|e| format!("获取合集失败: {}", e))?;
+    for proposal in &mut proposals {
+        let arcids: Vec<&str> = proposal
+            .members
+            .iter()
+            .map(|(a, _)| a.arcid.as_str())
+            .collect();
+        proposal.existing = tanks
+            .iter()
+            .filter(|tank| tank.archives.iter().any(|id| arcids.contains(&id.as_str())))
+            .max_by_key(|tank| tank.archives.len())
+            .map(|tank| (tank.id.clone(), tank.archives.clone()));
+        if let Some((id, _)) = &proposal.existing {
+            if let Some(tank) = tanks.iter().find(|tank| &tank.id == id) {
+                proposal.name = tank.name.clone();
+            }
+        }
+    }
+    // 已经完整在合集中的不再显示
+    proposals.retain(|proposal| !proposal.missing().is_empty());
+    if proposals.is_empty() {
+        println!("没有发现需要组成合集的作品");
+        return Ok(());
+    }
+    print_proposals(&proposals);
+    println!("共 {} 个合集建议", proposals.len().bright_green());
+    if options.dry_run {
+        return Ok(());
+    }
+    let selected: Vec<usize> = if options.yes {
+        (0..proposals.len()).collect()
+    } else {
+        confirm(proposals.len())
+    };
+    let mut failed = 0;
+    for i in selected {
+        let proposal = &proposals[i];
+        match write_proposal(settings, proposal).await {
+            Ok(id) => println!(
+                "✅{} {} 加入 {} 个作品",
+                proposal.name.bright_blue(),
+                id.cyan(),
+                proposal.missing().len().bright_green()
+            ),
+            Err(err) => {
+                failed += 1;
+                println!("❌{} {}", proposal.name, err.red());
+            }
+        }
+    }
+    if failed > 0 {
+        Err(format!("{} 个合集写入失败", failed))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(arcid: &str) -> Archive {
+        Archive {
+            arcid: arcid.to_string(),
+            ..Archive::default()
+        }
+    }
+
+    fn creators(tags: &[&str]) -> HashSet<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_cn_numbers() {
+        assert_eq!(parse_cn_number("三"), Some(3));
+        assert_eq!(parse_cn_number("十"), Some(10));
+        assert_eq!(parse_cn_number("十二"), Some(12));
+        assert_eq!(parse_cn_number("二十"), Some(20));
+        assert_eq!(parse_cn_number("九十九"), Some(99));
+        assert_eq!(parse_cn_number("百"), None);
+        assert_eq!(parse_cn_number("十十"), None);
+    }
+
+    #[test]
+    fn parse_volume_patterns() {
+        let volume = |title| parse_volume(title).map(|v| (v.series, v.key, v.order));
+        assert_eq!(
+            volume("[Artist] My  Series Vol.2 [Chinese]"),
+            Some(("My  Series".to_string(), "my series".to_string(), 2))
+        );
+        assert_eq!(volume("(C99) 物語 第十二話").map(|v| v.2), Some(12));
+        assert_eq!(volume("物語 後編").map(|v| v.2), Some(3));
+        assert_eq!(volume("Story #4").map(|v| v.2), Some(4));
+        assert_eq!(
+            volume("Story - 3").map(|v| (v.0, v.2)),
+            Some(("Story".to_string(), 3))
+        );
+        assert!(volume("Standalone").is_none());
+        // 只有卷号没有系列名
+        assert!(volume("[Artist] Vol.1").is_none());
+    }
+
+    #[test]
+    fn untagged_volume_does_not_bridge_creators() {
+        let titles = ["Series Vol.1", "Series Vol.2", "Series Vol.3"];
+        let volumes: Vec<_> = titles.iter().map(|t| parse_volume(t)).collect();
+        let creators = vec![
+            creators(&["artist:a"]),
+            creators(&[]),
+            creators(&["artist:c"]),
+        ];
+        let mut groups = Groups::new(3);
+        let mut reasons = HashMap::new();
+        group_series(&volumes, &creators, &mut groups, &mut reasons);
+        assert_eq!(groups.find(0), groups.find(1));
+        assert_ne!(groups.find(1), groups.find(2));
+    }
+
+    #[test]
+    fn ordered_keeps_other_tank_archives_after_members() {
+        let proposal = Proposal {
+            name: "Series".to_string(),
+            members: vec![(archive("a"), Some(1)), (archive("b"), Some(2))],
+            reasons: BTreeSet::new(),
+            existing: Some(("TANK_1".to_string(), vec!["x".into(), "b".into()])),
+        };
+        assert_eq!(proposal.ordered(), ["a", "b", "x"]);
+        assert_eq!(proposal.missing(), ["a"]);
+    }
+}
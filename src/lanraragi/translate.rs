@@ -0,0 +1,32 @@
+use super::archive::Archive;
+use super::config::Settings;
+use super::rewrite::{apply_changes, TagChange};
+use super::tag::{translate_tag, CnTags};
+
+// 这些 namespace 的值不是 EH 标签，保持原样
+const UNTOUCHED_NAMESPACES: [&str; 2] = ["source", "date_added"];
+
+/// 翻译一组 EH 格式的标签，`source` 等保持原样
+pub fn translate_tags(tags: Vec<String>, cn_tags: &CnTags) -> Vec<String> {
+    tags.into_iter()
+        .filter_map(|tag| match tag.split_once(':') {
+            Some((namespace, _)) if UNTOUCHED_NAMESPACES.contains(&namespace) => Some(tag),
+            Some(_) => translate_tag(cn_tags, &tag),
+            None => Some(tag),
+        })
+        .collect()
+}
+
+/// 把库里已有的英文 EH 标签翻译成中文，不访问 EH
+pub async fn translate_library(
+    settings: &Settings,
+    archives: &[Archive],
+    cn_tags: &CnTags,
+    dry_run: bool,
+) {
+    let changes: Vec<TagChange> = archives
+        .iter()
+        .filter_map(|archive| TagChange::new(archive, translate_tags(archive.tag_list(), cn_tags)))
+        .collect();
+    apply_changes(settings, &changes, dry_run, false).await;
+}
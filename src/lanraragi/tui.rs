@@ -0,0 +1,585 @@
+use super::archive::Archive;
+use super::checkpoint::{record_outcome, write_lock, Outcome};
+use super::commands::{
+    accept_candidate, fallback_or_skip, prefetch_top, search_candidates, skip_archive, Candidates,
+};
+use super::config::Settings;
+use super::eh::{evict_galleries, GL};
+use super::hash::{or_hash_search, HASH_QUERY};
+use super::progress::{capture_log, release_log, take_log};
+use super::store::{record_archive, record_decision, Decision};
+use super::tag::{translate_tag, CnTags, TagDictionary};
+use super::unhandle::{ignore_no_handle, Reason};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use regex::Regex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 终端颜色代码，界面中显示输出时去掉
+fn regex_ansi() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap())
+}
+
+struct Item {
+    archive: Archive,
+    query: String,
+    /// 搜索失败时为未处理原因和错误信息
+    candidates: Result<Candidates, (Reason, String)>,
+    list: ListState,
+    /// 是否已经尝试过自动选择
+    auto_checked: bool,
+    /// 本次运行中的处理结果
+    status: Option<&'static str>,
+}
+
+impl Item {
+    fn new(
+        archive: Archive,
+        query: String,
+        candidates: Result<Candidates, (Reason, String)>,
+    ) -> Self {
+        let mut list = ListState::default();
+        if candidates.as_ref().is_ok_and(|c| !c.gls.is_empty()) {
+            list.select(Some(0));
+        }
+        Self {
+            archive,
+            query,
+            candidates,
+            list,
+            auto_checked: false,
+            status: None,
+        }
+    }
+
+    fn candidates(&self) -> Option<&Candidates> {
+        self.candidates.as_ref().ok().filter(|c| !c.gls.is_empty())
+    }
+
+    /// 处理完或重新搜索后丢弃预取的画廊页
+    fn forget_prefetched(&self) {
+        if let Some(candidates) = self.candidates() {
+            evict_galleries(candidates.gls.iter().map(GL::url));
+        }
+    }
+}
+
+async fn search(settings: &Settings, archive: Archive, query: String) -> Item {
+    let result = if query.is_empty() {
+        None
+    } else {
+        Some(search_candidates(settings, &archive, &query).await)
+    };
+    let mut query = query;
+    let candidates = match or_hash_search(settings, &archive, result).await {
+        Some(Ok(candidates)) => {
+            prefetch_top(settings, &candidates).await;
+            // 标题无法提取关键词时结果来自页面哈希
+            if query.is_empty() {
+                query = HASH_QUERY.to_string();
+            }
+            Ok(candidates)
+        }
+        Some(Err(err)) => Err((Reason::Error, err.to_string())),
+        None => Err((Reason::NoTitleMatch, "标题无法提取搜索关键词".to_string())),
+    };
+    Item::new(archive, query, candidates)
+}
+
+/// 按顺序在后台搜索作品，最多领先 `prefetch` 个
+fn spawn_loader(
+    settings: &Settings,
+    archives: Vec<Archive>,
+    prefetch: usize,
+) -> mpsc::Receiver<Item> {
+    let (tx, rx) = mpsc::channel(prefetch.max(1));
+    let settings = settings.clone();
+    tokio::spawn(async move {
+        for archive in archives {
+            record_archive(&archive);
+            let query = archive.regex_title();
+            let item = search(&settings, archive, query).await;
+            if tx.send(item).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+enum Mode {
+    Normal,
+    /// 正在编辑搜索关键词
+    Query(String),
+}
+
+struct App<'a> {
+    settings: &'a Settings,
+    dict: &'a TagDictionary,
+    tag_cn: &'a CnTags,
+    items: Vec<Item>,
+    total: usize,
+    current: usize,
+    mode: Mode,
+    message: String,
+    handled: usize,
+}
+
+enum Action {
+    None,
+    Accept(Decision),
+    Skip,
+    Ignore,
+    Search(String),
+    Quit,
+}
+
+impl App<'_> {
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+
+        let Some(item) = self.items.get_mut(self.current) else {
+            frame.render_widget(
+                Paragraph::new(format!("[{}/{}] 搜索中...", self.current + 1, self.total))
+                    .block(Block::bordered()),
+                header,
+            );
+            return;
+        };
+
+        let mut title = vec![
+            Span::styled(
+                format!("[{}/{}] ", self.current + 1, self.total),
+                Style::new().fg(Color::Cyan),
+            ),
+            Span::styled(
+                format!("{} - {}", item.archive.title, item.archive.pagecount),
+                Style::new().fg(Color::LightBlue),
+            ),
+        ];
+        if let Some(status) = item.status {
+            title.push(Span::styled(
+                format!("  {}", status),
+                Style::new().fg(Color::Yellow),
+            ));
+        }
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(title),
+                Line::from(format!("搜索: {}", item.query)).style(Style::new().fg(Color::Gray)),
+            ])
+            .block(Block::bordered()),
+            header,
+        );
+
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(body);
+        match &item.candidates {
+            Ok(candidates) if !candidates.gls.is_empty() => {
+                let matches = candidates.matches();
+                let rows: Vec<ListItem> = candidates
+                    .gls
+                    .iter()
+                    .zip(&candidates.features)
+                    .enumerate()
+                    .map(|(i, (gl, features))| {
+                        let page_style = if features.page_match {
+                            Style::new().fg(Color::Green)
+                        } else {
+                            Style::new().fg(Color::Blue)
+                        };
+                        let mut spans = vec![];
+                        // 页面哈希的结果按命中页数排列，显示命中数
+                        if let Some(matches) = matches {
+                            spans.push(Span::styled(
+                                format!("#{} ", matches[i]),
+                                Style::new().fg(Color::Cyan),
+                            ));
+                        }
+                        spans.extend([
+                            Span::styled(
+                                format!("{:.2} ", self.settings.scoring.score(features)),
+                                Style::new().fg(Color::LightGreen),
+                            ),
+                            Span::styled(
+                                format!("{:>5.1}% ", features.similarity * 100.0),
+                                Style::new().fg(Color::Green),
+                            ),
+                            Span::styled(format!("{} ", gl.pages()), page_style),
+                            Span::styled(
+                                format!("{} ", gl.datetime()),
+                                Style::new().fg(Color::Yellow),
+                            ),
+                            Span::styled(gl.title().to_string(), Style::new().fg(Color::Magenta)),
+                        ]);
+                        ListItem::new(Line::from(spans))
+                    })
+                    .collect();
+                let list = List::new(rows)
+                    .block(Block::bordered().title("候选"))
+                    .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+                frame.render_stateful_widget(list, left, &mut item.list);
+            }
+            Ok(_) => frame.render_widget(
+                Paragraph::new("没有搜索结果").block(Block::bordered().title("候选")),
+                left,
+            ),
+            Err((_, err)) => frame.render_widget(
+                Paragraph::new(err.as_str())
+                    .style(Style::new().fg(Color::Red))
+                    .wrap(Wrap { trim: false })
+                    .block(Block::bordered().title("候选")),
+                left,
+            ),
+        }
+
+        let preview = match (item.candidates(), item.list.selected()) {
+            (Some(candidates), Some(index)) => {
+                let gl = &candidates.gls[index];
+                let mut lines =
+                    vec![Line::from(gl.url().to_string()).style(Style::new().fg(Color::DarkGray))];
+                for raw_tag in gl.raw_tags() {
+                    let Some(tag) = translate_tag(self.tag_cn, raw_tag) else {
+                        continue;
+                    };
+                    let mut spans = vec![Span::raw(tag.clone())];
+                    if tag == raw_tag {
+                        spans[0] = Span::styled(tag, Style::new().fg(Color::Yellow));
+                    } else if let Some(intro) = self.dict.describe(raw_tag) {
+                        spans.push(Span::styled(
+                            format!("  {}", intro),
+                            Style::new().fg(Color::DarkGray),
+                        ));
+                    }
+                    lines.push(Line::from(spans));
+                }
+                lines
+            }
+            _ => vec![],
+        };
+        frame.render_widget(
+            Paragraph::new(preview)
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title("标签预览")),
+            right,
+        );
+
+        let footer_text =
+            match &self.mode {
+                Mode::Normal => Line::from(vec![
+                Span::styled(format!("{}  ", self.message), Style::new().fg(Color::Yellow)),
+                Span::styled(
+                    "↑↓ 选择  Enter 确认  s 跳过  i 永久忽略  / 重新搜索  o 打开  b 上一个  n 下一个  q 退出",
+                    Style::new().fg(Color::DarkGray),
+                ),
+            ]),
+                Mode::Query(query) => Line::from(vec![
+                    Span::styled("搜索: ", Style::new().fg(Color::Cyan)),
+                    Span::raw(query.clone()),
+                    Span::styled("▏", Style::new().fg(Color::Cyan)),
+                ]),
+            };
+        frame.render_widget(Paragraph::new(footer_text).block(Block::bordered()), footer);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Action::Quit;
+        }
+        if let Mode::Query(query) = &mut self.mode {
+            match key.code {
+                KeyCode::Enter => {
+                    let query = query.trim().to_string();
+                    self.mode = Mode::Normal;
+                    if !query.is_empty() {
+                        return Action::Search(query);
+                    }
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Char(c) => query.push(c),
+                _ => {}
+            }
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('b') | KeyCode::Left => {
+                self.current = self.current.saturating_sub(1);
+                self.message.clear();
+                return Action::None;
+            }
+            // 回看后回到还没处理的作品
+            KeyCode::Char('n') | KeyCode::Right => {
+                if self
+                    .items
+                    .get(self.current)
+                    .is_some_and(|item| item.status.is_some())
+                {
+                    self.current += 1;
+                    self.message.clear();
+                }
+                return Action::None;
+            }
+            _ => {}
+        }
+        let Some(item) = self.items.get_mut(self.current) else {
+            return Action::None;
+        };
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => item.list.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => {
+                let len = item.candidates().map_or(0, |c| c.gls.len());
+                if item.list.selected().is_some_and(|i| i + 1 < len) {
+                    item.list.select_next();
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('a') if item.candidates().is_some() => {
+                return Action::Accept(Decision::Selected)
+            }
+            KeyCode::Char('s') => return Action::Skip,
+            KeyCode::Char('i') => return Action::Ignore,
+            KeyCode::Char('/') if item.status.is_none() => {
+                // 页面哈希的结果没有可以修改的标题关键词
+                let query = if item.query == HASH_QUERY {
+                    String::new()
+                } else {
+                    item.query.clone()
+                };
+                self.mode = Mode::Query(query)
+            }
+            KeyCode::Char('o') => {
+                if let (Some(candidates), Some(index)) = (item.candidates(), item.list.selected()) {
+                    if let Err(err) = open::that(candidates.gls[index].url()) {
+                        self.message = format!("无法打开浏览器: {}", err);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn advance(&mut self, status: &'static str) {
+        let item = &mut self.items[self.current];
+        item.forget_prefetched();
+        item.status = Some(status);
+        self.message.clear();
+        self.current += 1;
+    }
+
+    async fn perform(&mut self, action: Action) {
+        let Some(item) = self.items.get(self.current) else {
+            return;
+        };
+        // 回看已处理的作品时只能查看，结果已经写入和记录
+        if let Some(status) = item.status {
+            if !matches!(action, Action::None | Action::Quit) {
+                self.message = format!("该作品{}，按 n 前往下一个", status);
+            }
+            return;
+        }
+        match action {
+            Action::Accept(decision) => {
+                let (Some(candidates), Some(index)) = (item.candidates(), item.list.selected())
+                else {
+                    return;
+                };
+                if let Err(err) = accept_candidate(
+                    self.settings,
+                    &item.archive,
+                    &candidates.gls[index],
+                    self.tag_cn,
+                    Some(candidates.search_id),
+                    decision,
+                )
+                .await
+                {
+                    self.message = format!("写入失败: {}", err);
+                    return;
+                }
+                self.handled += 1;
+                self.advance(if decision == Decision::Auto {
+                    "已自动写入"
+                } else {
+                    "已写入"
+                });
+            }
+            Action::Skip => {
+                let (reason, message) = match &item.candidates {
+                    Ok(candidates) if !candidates.gls.is_empty() => {
+                        record_decision(
+                            &item.archive.arcid,
+                            Some(candidates.search_id),
+                            Decision::Skipped,
+                            None,
+                        );
+                        (Reason::UserSkipped, None)
+                    }
+                    Ok(_) => (Reason::NoResults, None),
+                    Err((reason, err)) => (*reason, Some(err.clone())),
+                };
+                let queries = vec![item.query.clone()];
+                if reason == Reason::UserSkipped {
+                    skip_archive(self.settings, &item.archive, reason, queries, message).await;
+                    self.advance("已跳过");
+                    return;
+                }
+                // 搜索失败的作品先尝试插件
+                let archive = item.archive.clone();
+                let tagged = fallback_or_skip(
+                    self.settings,
+                    &archive,
+                    self.tag_cn,
+                    reason,
+                    queries,
+                    message,
+                )
+                .await;
+                if tagged {
+                    self.handled += 1;
+                    self.advance("插件已写入");
+                } else {
+                    self.advance("已跳过");
+                }
+            }
+            Action::Ignore => {
+                {
+                    let _guard = write_lock().lock().await;
+                    ignore_no_handle(std::slice::from_ref(&item.archive));
+                    record_outcome(&item.archive.arcid, Outcome::Unhandled(Reason::Ignored));
+                }
+                self.advance("已忽略");
+            }
+            Action::Search(query) => {
+                item.forget_prefetched();
+                let archive = item.archive.clone();
+                let mut searched = search(self.settings, archive, query).await;
+                searched.auto_checked = true;
+                self.items[self.current] = searched;
+            }
+            Action::None | Action::Quit => {}
+        }
+    }
+
+    /// 把写入、跳过时收集到的输出接在提示后面显示，不直接打印到全屏界面上
+    fn show_log(&mut self) {
+        let lines = take_log();
+        if lines.is_empty() {
+            return;
+        }
+        let mut parts = vec![];
+        if !self.message.is_empty() {
+            parts.push(self.message.clone());
+        }
+        parts.extend(
+            lines
+                .iter()
+                .map(|line| regex_ansi().replace_all(line, "").into_owned()),
+        );
+        self.message = parts.join("  ");
+    }
+
+    /// 第一次显示某个作品时尝试自动选择
+    fn auto_action(&mut self) -> Option<Action> {
+        let item = self.items.get_mut(self.current)?;
+        if item.auto_checked {
+            return None;
+        }
+        item.auto_checked = true;
+        let index = item.candidates()?.auto_select(&self.settings.scoring)?;
+        item.list.select(Some(index));
+        Some(Action::Accept(Decision::Auto))
+    }
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    app: &mut App<'_>,
+    loader: &mut mpsc::Receiver<Item>,
+) -> std::io::Result<bool> {
+    loop {
+        while let Ok(item) = loader.try_recv() {
+            app.items.push(item);
+        }
+        if app.current >= app.total {
+            return Ok(true);
+        }
+        terminal.draw(|frame| app.draw(frame))?;
+        let action = match app.auto_action() {
+            Some(action) => action,
+            None => {
+                if !event::poll(Duration::from_millis(100))? {
+                    continue;
+                }
+                match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => app.handle_key(key),
+                    _ => continue,
+                }
+            }
+        };
+        match action {
+            Action::Quit => return Ok(false),
+            Action::None => {}
+            Action::Search(_) => {
+                app.message = "搜索中...".to_string();
+                terminal.draw(|frame| app.draw(frame))?;
+                app.perform(action).await;
+                app.message.clear();
+                app.show_log();
+            }
+            action => {
+                app.perform(action).await;
+                app.show_log();
+            }
+        }
+    }
+}
+
+/// 全屏界面逐个选择候选作品，返回写入的数量以及是否全部处理完
+///
+/// 终端出错时返回错误，已写入的数量仍然有效
+pub async fn review(
+    settings: &Settings,
+    archives: Vec<Archive>,
+    dict: &TagDictionary,
+    tag_cn: &CnTags,
+    prefetch: usize,
+) -> (usize, std::io::Result<bool>) {
+    let mut app = App {
+        settings,
+        dict,
+        tag_cn,
+        items: vec![],
+        total: archives.len(),
+        current: 0,
+        mode: Mode::Normal,
+        message: String::new(),
+        handled: 0,
+    };
+    let mut loader = spawn_loader(settings, archives, prefetch);
+    let mut terminal = ratatui::init();
+    capture_log();
+    let result = event_loop(&mut terminal, &mut app, &mut loader).await;
+    ratatui::restore();
+    for line in release_log() {
+        println!("{}", line);
+    }
+    (app.handled, result)
+}
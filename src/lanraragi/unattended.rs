@@ -0,0 +1,293 @@
+use super::archive::Archive;
+use super::checkpoint::{handle_interrupt, write_lock};
+use super::commands::{fallback_or_skip, search_candidates, skip_archive, write_tags, Candidates};
+use super::config::Settings;
+use super::eh::GL;
+use super::hash::{hash_search, HASH_QUERY};
+use super::progress::{log, multi_progress};
+use super::store::{record_archive, record_decision, Decision};
+use super::tag::CnTags;
+use super::unhandle::Reason;
+
+use futures_util::FutureExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use owo_colors::OwoColorize;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+
+/// 每个工作任务最多领先写入的作品数，限制等待写入的结果数量
+static AHEAD_PER_JOB: usize = 2;
+
+/// 工作任务的结果，按原来的顺序写入
+enum Worked {
+    /// 自动选中的候选和翻译好的标签
+    Accepted {
+        search_id: i64,
+        url: String,
+        tags: String,
+    },
+    Unhandled {
+        reason: Reason,
+        queries: Vec<String>,
+        message: Option<String>,
+    },
+}
+
+impl Worked {
+    fn unhandled(reason: Reason, queries: Vec<String>, message: Option<String>) -> Self {
+        Self::Unhandled {
+            reason,
+            queries,
+            message,
+        }
+    }
+}
+
+/// 按标题搜索并自动选择，失败时返回未处理的结果
+async fn pick_by_title(
+    settings: &Settings,
+    archive: &Archive,
+) -> Result<(i64, GL, String), Worked> {
+    let query = archive.regex_title();
+    if query.is_empty() {
+        return Err(Worked::unhandled(Reason::NoTitleMatch, vec![], None));
+    }
+    let Candidates {
+        search_id,
+        mut gls,
+        features,
+        ..
+    } = match search_candidates(settings, archive, &query).await {
+        Ok(candidates) => candidates,
+        Err(err) => {
+            let message = Some(err.to_string());
+            return Err(Worked::unhandled(Reason::Error, vec![query], message));
+        }
+    };
+    if gls.is_empty() {
+        return Err(Worked::unhandled(Reason::NoResults, vec![query], None));
+    }
+    let Some(index) = settings.scoring.auto_select(&features) else {
+        return Err(Worked::unhandled(Reason::LowScore, vec![query], None));
+    };
+    Ok((search_id, gls.swap_remove(index), query))
+}
+
+/// 按页面哈希搜索，只采用唯一命中多页的画廊
+async fn pick_by_hash(settings: &Settings, archive: &Archive) -> Option<(i64, GL, String)> {
+    if !settings.hash_search {
+        return None;
+    }
+    let found = match hash_search(settings, archive).await {
+        Ok(found) => found,
+        Err(err) => {
+            log(format!("❌hash search failed: {}", err.red()));
+            return None;
+        }
+    };
+    let index = found.hash.as_ref()?.confident()?;
+    let mut gls = found.gls;
+    Some((
+        found.search_id,
+        gls.swap_remove(index),
+        HASH_QUERY.to_string(),
+    ))
+}
+
+/// 搜索、自动选择并下载标签，不写入
+async fn work(settings: &Settings, archive: &Archive, tag_cn: &CnTags) -> Worked {
+    record_archive(archive);
+    let (search_id, gl, query) = match pick_by_title(settings, archive).await {
+        Ok(picked) => picked,
+        // 标题找不到时最后再试页面哈希
+        Err(unhandled) => match pick_by_hash(settings, archive).await {
+            Some(picked) => picked,
+            None => return unhandled,
+        },
+    };
+    match gl.get_tags_from_eh_gl(settings, tag_cn).await {
+        Ok(tags) => Worked::Accepted {
+            search_id,
+            url: gl.url().to_string(),
+            tags,
+        },
+        Err(err) => Worked::unhandled(Reason::Error, vec![query], Some(err.to_string())),
+    }
+}
+
+/// 写入一个结果并记录到运行状态，成功写入标签时返回 true
+async fn commit(settings: &Settings, archive: &Archive, tag_cn: &CnTags, worked: Worked) -> bool {
+    match worked {
+        Worked::Accepted {
+            search_id,
+            url,
+            tags,
+        } => {
+            record_decision(&archive.arcid, Some(search_id), Decision::Auto, Some(&url));
+            let result = {
+                let _guard = write_lock().lock().await;
+                write_tags(settings, archive, &tags).await
+            };
+            match result {
+                Ok(()) => {
+                    log(format!("✅{}", archive.title.bright_blue()));
+                    true
+                }
+                Err(err) => {
+                    log(format!("❌write failed: {} {}", archive.title, err.red()));
+                    skip_archive(
+                        settings,
+                        archive,
+                        Reason::Error,
+                        vec![],
+                        Some(err.to_string()),
+                    )
+                    .await;
+                    false
+                }
+            }
+        }
+        Worked::Unhandled {
+            reason,
+            queries,
+            message,
+        } => {
+            log(format!("❌{:?}: {}", reason, archive.title.bright_yellow()));
+            // 有候选只是分数不够时留给人工审核，不用插件的结果覆盖
+            if reason == Reason::LowScore {
+                skip_archive(settings, archive, reason, queries, message).await;
+                return false;
+            }
+            fallback_or_skip(settings, archive, tag_cn, reason, queries, message).await
+        }
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "未知错误".to_string());
+    format!("panic: {}", message)
+}
+
+fn spawn_worker(
+    worker: usize,
+    settings: &Settings,
+    archives: Arc<Vec<Archive>>,
+    tag_cn: Arc<CnTags>,
+    next: Arc<AtomicUsize>,
+    window: Arc<Semaphore>,
+    tx: mpsc::UnboundedSender<(usize, Worked, OwnedSemaphorePermit)>,
+) {
+    let settings = settings.clone();
+    let bar = multi_progress().add(ProgressBar::new_spinner());
+    bar.set_style(ProgressStyle::with_template("{spinner:.green} [{prefix}] {wide_msg}").unwrap());
+    bar.set_prefix((worker + 1).to_string());
+    bar.enable_steady_tick(Duration::from_millis(120));
+    tokio::spawn(async move {
+        loop {
+            let permit = window.clone().acquire_owned().await.unwrap();
+            let index = next.fetch_add(1, Ordering::SeqCst);
+            let Some(archive) = archives.get(index) else {
+                break;
+            };
+            bar.set_message(archive.title.clone());
+            // 一个作品出错不能让其他任务一直等待它的结果
+            let worked = AssertUnwindSafe(work(&settings, archive, &tag_cn))
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|panic| {
+                    Worked::unhandled(Reason::Error, vec![], Some(panic_message(panic)))
+                });
+            if tx.send((index, worked, permit)).is_err() {
+                break;
+            }
+        }
+        bar.finish_and_clear();
+    });
+}
+
+/// 用 `jobs` 个任务并发搜索和下载标签，所有任务共用EH的请求间隔；
+/// 结果按作品顺序逐个写入，中断后可以用 `--resume` 继续。返回成功写入的数量
+pub async fn process_unattended(
+    settings: &Settings,
+    archives: Vec<Archive>,
+    tag_cn: &CnTags,
+    jobs: usize,
+) -> usize {
+    let interrupt = handle_interrupt();
+    let jobs = jobs.max(1);
+    let archives = Arc::new(archives);
+    let tag_cn = Arc::new(tag_cn.clone());
+    let next = Arc::new(AtomicUsize::new(0));
+    let window = Arc::new(Semaphore::new(jobs * AHEAD_PER_JOB));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let total = multi_progress().add(ProgressBar::new(archives.len() as u64));
+    total.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
+        )
+        .unwrap()
+        .progress_chars("#>-"),
+    );
+    for worker in 0..jobs {
+        spawn_worker(
+            worker,
+            settings,
+            archives.clone(),
+            tag_cn.clone(),
+            next.clone(),
+            window.clone(),
+            tx.clone(),
+        );
+    }
+    drop(tx);
+
+    // 先完成的结果在这里等待前面的作品写完
+    let mut ready = BTreeMap::new();
+    let mut committed = 0;
+    let mut handled = 0;
+    while let Some((index, worked, permit)) = rx.recv().await {
+        ready.insert(index, (worked, permit));
+        while let Some((worked, _permit)) = ready.remove(&committed) {
+            if commit(settings, &archives[committed], &tag_cn, worked).await {
+                handled += 1;
+            }
+            committed += 1;
+            total.inc(1);
+        }
+    }
+    total.finish();
+    interrupt.abort();
+    log(format!(
+        "已写入 {}/{}",
+        handled.bright_green(),
+        archives.len().cyan()
+    ));
+    handled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn panic_becomes_message() {
+        let result = AssertUnwindSafe(async { panic!("no index") })
+            .catch_unwind()
+            .await;
+        assert_eq!(panic_message(result.unwrap_err()), "panic: no index");
+        let result = AssertUnwindSafe(async { panic!("page {}", 3) })
+            .catch_unwind()
+            .await;
+        assert_eq!(panic_message(result.unwrap_err()), "panic: page 3");
+    }
+}
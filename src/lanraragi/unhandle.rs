@@ -1,34 +1,239 @@
 use super::archive::Archive;
+use super::progress::log;
+use super::store::store;
 
-use std::fs::{read, write};
+use chrono::Utc;
+use clap::ValueEnum;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Params, ToSql};
+use serde::{Deserialize, Serialize};
+use std::fs::{read, rename};
 use std::path::Path;
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Mutex, OnceLock, PoisonError};
 
 static NO_HANDLE_FILE_NAME: &str = "no_handle.json";
 
-pub fn no_handle_file() -> &'static Mutex<Vec<Archive>> {
-    static NO_HANDLE_FILE: OnceLock<Mutex<Vec<Archive>>> = OnceLock::new();
-    NO_HANDLE_FILE.get_or_init(|| {
-        // check if file exists
-        if !Path::new(NO_HANDLE_FILE_NAME).exists() {
-            return Mutex::new(Vec::new());
-        }
-        let no_handle_data = String::from_utf8(read(NO_HANDLE_FILE_NAME).unwrap()).unwrap();
-        if no_handle_data.is_empty() {
-            return Mutex::new(Vec::new());
-        }
-        Mutex::new(serde_json::from_str::<Vec<Archive>>(&no_handle_data).unwrap())
-    })
-}
-
-pub fn add_and_save_no_handle(data: Archive) {
-    let mut no_handle_array = no_handle_file().lock().unwrap();
-    no_handle_array.push(data);
-    write(
+/// 作品没有被处理的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// 标题无法提取搜索关键词
+    NoTitleMatch,
+    /// 搜索没有结果
+    NoResults,
+    /// 用户跳过
+    UserSkipped,
+    /// 无人值守时最高评分低于自动选择阈值
+    LowScore,
+    /// 请求或解析出错
+    Error,
+    /// 永久忽略，不再处理
+    Ignored,
+    /// 旧版 no_handle.json 导入，原因未知
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueueEntry {
+    pub archive: Archive,
+    pub reason: Reason,
+    pub time: i64,
+    /// 尝试过的搜索关键词
+    #[serde(default)]
+    pub queries: Vec<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// 把旧版 no_handle.json (作品数组) 导入数据库，导入后改名为 no_handle.json.imported
+fn import_legacy() {
+    if !Path::new(NO_HANDLE_FILE_NAME).exists() {
+        return;
+    }
+    let no_handle_data = String::from_utf8(read(NO_HANDLE_FILE_NAME).unwrap()).unwrap();
+    let archives: Vec<Archive> = if no_handle_data.trim().is_empty() {
+        vec![]
+    } else {
+        serde_json::from_str(&no_handle_data).unwrap()
+    };
+    let entries: Vec<QueueEntry> = archives
+        .into_iter()
+        .map(|archive| QueueEntry {
+            archive,
+            reason: Reason::Unknown,
+            time: Utc::now().timestamp(),
+            queries: vec![],
+            message: None,
+        })
+        .collect();
+    let mut conn = store().lock().unwrap_or_else(PoisonError::into_inner);
+    let tx = conn.transaction().unwrap();
+    for entry in &entries {
+        // 数据库中已有的记录优先
+        tx.execute(
+            "INSERT OR IGNORE INTO queue (arcid, archive, reason, queries, message, time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params_from_iter(entry_params(entry)),
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+    rename(
         NO_HANDLE_FILE_NAME,
-        serde_json::to_string_pretty(&*no_handle_array)
-            .unwrap()
-            .as_bytes(),
+        format!("{}.imported", NO_HANDLE_FILE_NAME),
     )
     .unwrap();
+    log(format!(
+        "已从 {} 导入 {} 条未处理作品",
+        NO_HANDLE_FILE_NAME,
+        entries.len()
+    ));
+}
+
+fn reason_str(reason: Reason) -> String {
+    serde_json::to_value(reason)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+fn entry_params(entry: &QueueEntry) -> [Box<dyn ToSql>; 6] {
+    [
+        Box::new(entry.archive.arcid.clone()),
+        Box::new(serde_json::to_string(&entry.archive).unwrap()),
+        Box::new(reason_str(entry.reason)),
+        Box::new(serde_json::to_string(&entry.queries).unwrap()),
+        Box::new(entry.message.clone()),
+        Box::new(entry.time),
+    ]
+}
+
+/// 未处理列表所在的数据库，第一次使用时导入旧版文件
+fn queue_store() -> &'static Mutex<Connection> {
+    static IMPORTED: OnceLock<()> = OnceLock::new();
+    IMPORTED.get_or_init(import_legacy);
+    store()
+}
+
+fn query_entries(condition: &str, params: impl Params) -> Vec<QueueEntry> {
+    let conn = queue_store().lock().unwrap_or_else(PoisonError::into_inner);
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT archive, reason, queries, message, time FROM queue {} ORDER BY arcid",
+            condition
+        ))
+        .unwrap();
+    let rows = stmt
+        .query_map(params, |row| {
+            let archive: String = row.get(0)?;
+            let reason: String = row.get(1)?;
+            let queries: String = row.get(2)?;
+            Ok(QueueEntry {
+                archive: serde_json::from_str(&archive).unwrap(),
+                reason: serde_json::from_value(reason.into()).unwrap_or(Reason::Unknown),
+                queries: serde_json::from_str(&queries).unwrap_or_default(),
+                message: row.get(3)?,
+                time: row.get(4)?,
+            })
+        })
+        .unwrap();
+    rows.map(Result::unwrap).collect()
+}
+
+/// 加入或更新未处理列表；已忽略的作品保持忽略
+pub fn add_and_save_no_handle(
+    data: Archive,
+    reason: Reason,
+    queries: Vec<String>,
+    message: Option<String>,
+) {
+    let entry = QueueEntry {
+        archive: data,
+        reason,
+        time: Utc::now().timestamp(),
+        queries,
+        message,
+    };
+    queue_store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute(
+            "INSERT INTO queue (arcid, archive, reason, queries, message, time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (arcid) DO UPDATE SET
+                 archive = excluded.archive, reason = excluded.reason, queries = excluded.queries,
+                 message = excluded.message, time = excluded.time
+             WHERE queue.reason != 'ignored'",
+            params_from_iter(entry_params(&entry)),
+        )
+        .unwrap();
+}
+
+/// 作品成功写入标签后移出列表
+pub fn remove_no_handle(arcid: &str) {
+    queue_store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .execute("DELETE FROM queue WHERE arcid = ?1", params![arcid])
+        .unwrap();
+}
+
+pub fn is_ignored(arcid: &str) -> bool {
+    queue_store()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .query_row(
+            "SELECT 1 FROM queue WHERE arcid = ?1 AND reason = 'ignored'",
+            params![arcid],
+            |_| Ok(()),
+        )
+        .optional()
+        .unwrap()
+        .is_some()
+}
+
+/// 列表中的全部作品，含已忽略的
+pub fn all_entries() -> Vec<QueueEntry> {
+    query_entries("", [])
+}
+
+/// 等待重试的作品，不含已忽略的
+pub fn queued_entries() -> Vec<QueueEntry> {
+    query_entries("WHERE reason != 'ignored'", [])
+}
+
+/// 永久忽略这些作品，不在列表中的也会加入
+pub fn ignore_no_handle(archives: &[Archive]) {
+    let mut conn = queue_store().lock().unwrap_or_else(PoisonError::into_inner);
+    let tx = conn.transaction().unwrap();
+    for archive in archives {
+        let entry = QueueEntry {
+            archive: archive.clone(),
+            reason: Reason::Ignored,
+            time: Utc::now().timestamp(),
+            queries: vec![],
+            message: None,
+        };
+        tx.execute(
+            "INSERT INTO queue (arcid, archive, reason, queries, message, time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (arcid) DO UPDATE SET reason = excluded.reason, time = excluded.time",
+            params_from_iter(entry_params(&entry)),
+        )
+        .unwrap();
+    }
+    tx.commit().unwrap();
+}
+
+/// 清空列表，`reason` 为空时清除除已忽略外的全部条目，返回清除的数量
+pub fn clear_no_handle(reason: Option<Reason>) -> usize {
+    let conn = queue_store().lock().unwrap_or_else(PoisonError::into_inner);
+    match reason {
+        Some(reason) => conn.execute(
+            "DELETE FROM queue WHERE reason = ?1",
+            params![reason_str(reason)],
+        ),
+        None => conn.execute("DELETE FROM queue WHERE reason != 'ignored'", []),
+    }
+    .unwrap()
 }
@@ -0,0 +1,100 @@
+use super::archive::Archive;
+
+use serde::{Deserialize, Serialize};
+
+/// 什么样的作品算作无标签、需要搜索
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UntaggedRule {
+    /// 这些namespace的标签不算有效标签，如插件自动添加的 `date_added`
+    pub ignore_namespaces: Vec<String>,
+    /// 有效标签少于该数量时算作无标签
+    pub min_tags: usize,
+    /// 没有 `source:` 标签的作品也算作无标签
+    pub missing_source: bool,
+}
+
+impl Default for UntaggedRule {
+    fn default() -> Self {
+        Self {
+            ignore_namespaces: vec!["date_added".to_string()],
+            min_tags: 1,
+            missing_source: false,
+        }
+    }
+}
+
+impl UntaggedRule {
+    fn is_ignored_tag(&self, tag: &str) -> bool {
+        let namespace = tag.split_once(':').map_or("", |(ns, _)| ns);
+        self.ignore_namespaces
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(namespace))
+    }
+
+    /// 判断作品是否算作无标签，并给出原因
+    pub fn judge(&self, archive: &Archive) -> (bool, String) {
+        let tags = archive.tag_list();
+        let meaningful = tags.iter().filter(|tag| !self.is_ignored_tag(tag)).count();
+        if meaningful < self.min_tags {
+            return (
+                true,
+                format!("有效标签 {} 个，少于 {}", meaningful, self.min_tags),
+            );
+        }
+        let has_source = tags.iter().any(|tag| tag.starts_with("source:"));
+        if self.missing_source && !has_source {
+            return (true, "没有 source 标签".to_string());
+        }
+        (
+            false,
+            format!(
+                "有效标签 {} 个{}",
+                meaningful,
+                if has_source {
+                    "，有 source 标签"
+                } else {
+                    ""
+                }
+            ),
+        )
+    }
+
+    pub fn is_untagged(&self, archive: &Archive) -> bool {
+        self.judge(archive).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(tags: &str) -> Archive {
+        Archive {
+            tags: tags.to_string(),
+            ..Archive::default()
+        }
+    }
+
+    #[test]
+    fn judge_counts_meaningful_tags() {
+        let rule = UntaggedRule::default();
+        assert!(rule.judge(&archive("")).0);
+        assert!(rule.judge(&archive("date_added:1700000000")).0);
+        assert!(rule.judge(&archive("DATE_ADDED:1700000000")).0);
+        assert!(!rule.judge(&archive("date_added:1, artist:a")).0);
+
+        let strict = UntaggedRule {
+            min_tags: 2,
+            missing_source: true,
+            ..UntaggedRule::default()
+        };
+        assert!(strict.judge(&archive("artist:a")).0);
+        let (untagged, reason) = strict.judge(&archive("artist:a, female:b"));
+        assert!(untagged);
+        assert_eq!(reason, "没有 source 标签");
+        let (untagged, reason) = strict.judge(&archive("artist:a, source:e-hentai.org/g/1/a"));
+        assert!(!untagged);
+        assert_eq!(reason, "有效标签 2 个，有 source 标签");
+    }
+}
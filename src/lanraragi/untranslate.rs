@@ -0,0 +1,129 @@
+use super::archive::Archive;
+use super::config::Settings;
+use super::rewrite::{apply_changes, TagChange};
+use super::tag::CnTags;
+
+use owo_colors::OwoColorize;
+use std::collections::HashMap;
+
+/// `中文namespace:中文tag` -> `namespace:tag`
+#[derive(Debug, Default)]
+pub struct ReverseIndex {
+    tags: HashMap<String, Vec<String>>,
+}
+
+impl ReverseIndex {
+    pub fn new(cn_tags: &CnTags) -> Self {
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for (raw_tag, cn_tag) in &cn_tags.tags {
+            // 保持英文的标签不需要还原
+            if cn_tag == raw_tag {
+                continue;
+            }
+            let raw_tags = tags.entry(cn_tag.clone()).or_default();
+            raw_tags.push(raw_tag.clone());
+        }
+        for raw_tags in tags.values_mut() {
+            raw_tags.sort();
+        }
+        Self { tags }
+    }
+
+    /// 多个英文标签共用同一个中文名的条目
+    pub fn ambiguous(&self) -> Vec<(&String, &Vec<String>)> {
+        let mut list: Vec<_> = self.tags.iter().filter(|(_, v)| v.len() > 1).collect();
+        list.sort();
+        list
+    }
+
+    /// 唯一对应时返回英文标签，有歧义时返回全部候选
+    pub fn get(&self, cn_tag: &str) -> Option<&Vec<String>> {
+        self.tags.get(cn_tag)
+    }
+}
+
+fn untranslate_tags(
+    archive: &Archive,
+    index: &ReverseIndex,
+    skipped: &mut HashMap<String, u32>,
+) -> Vec<String> {
+    archive
+        .tag_list()
+        .into_iter()
+        .map(|tag| match index.get(&tag) {
+            Some(raw_tags) if raw_tags.len() == 1 => raw_tags[0].clone(),
+            Some(_) => {
+                *skipped.entry(tag.clone()).or_default() += 1;
+                tag
+            }
+            None => tag,
+        })
+        .collect()
+}
+
+/// 把中文标签还原为 EH 的英文标签，`arcid` 为空时处理整个库
+pub async fn untranslate_library(
+    settings: &Settings,
+    archives: &[Archive],
+    cn_tags: &CnTags,
+    arcid: Option<&str>,
+    dry_run: bool,
+) {
+    let index = ReverseIndex::new(cn_tags);
+    let ambiguous = index.ambiguous();
+    if !ambiguous.is_empty() {
+        println!(
+            "词典中有 {} 个中文标签对应多个英文标签，这些标签不会被还原",
+            ambiguous.len().bright_yellow()
+        );
+    }
+
+    let mut skipped = HashMap::new();
+    let changes: Vec<TagChange> = archives
+        .iter()
+        .filter(|archive| arcid.is_none_or(|id| archive.arcid == id))
+        .filter_map(|archive| {
+            TagChange::new(archive, untranslate_tags(archive, &index, &mut skipped))
+        })
+        .collect();
+
+    for (tag, count) in &skipped {
+        println!(
+            "{} ({} 次) -> {}",
+            tag.bright_yellow(),
+            count,
+            index.get(tag).unwrap().join(" / ").red()
+        );
+    }
+    apply_changes(settings, &changes, dry_run, false).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reverse_index_skips_untranslated() {
+        let cn_tags = CnTags::new(
+            [
+                ("female:huge breasts", "女性:巨乳"),
+                ("female:big breasts", "女性:巨乳"),
+                ("artist:some one", "艺术家:某人"),
+                ("artist:kept", "artist:kept"),
+            ]
+            .into_iter()
+            .map(|(raw, cn)| (raw.to_string(), cn.to_string()))
+            .collect(),
+        );
+        let index = ReverseIndex::new(&cn_tags);
+        assert_eq!(index.get("艺术家:某人").unwrap(), &["artist:some one"]);
+        assert_eq!(
+            index.get("女性:巨乳").unwrap(),
+            &["female:big breasts", "female:huge breasts"]
+        );
+        assert!(index.get("artist:kept").is_none());
+        let ambiguous = index.ambiguous();
+        assert_eq!(ambiguous.len(), 1);
+        assert_eq!(ambiguous[0].0, "女性:巨乳");
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::write;
+use std::sync::{Mutex, OnceLock};
+
+static UNTRANSLATED_CSV_NAME: &str = "untranslated.csv";
+static UNTRANSLATED_MD_NAME: &str = "untranslated.md";
+
+#[derive(Debug, Clone)]
+pub struct Untranslated {
+    pub count: u32,
+    pub example_url: String,
+}
+
+/// 本次运行中没有中文翻译的标签，key 为 `namespace:tag`
+pub fn untranslated_tags() -> &'static Mutex<HashMap<String, Untranslated>> {
+    static UNTRANSLATED: OnceLock<Mutex<HashMap<String, Untranslated>>> = OnceLock::new();
+    UNTRANSLATED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn record_untranslated(raw_tag: &str, url: &str) {
+    let mut tags = untranslated_tags().lock().unwrap();
+    tags.entry(raw_tag.to_string())
+        .or_insert_with(|| Untranslated {
+            count: 0,
+            example_url: url.to_string(),
+        })
+        .count += 1;
+}
+
+fn sorted(tags: &HashMap<String, Untranslated>) -> Vec<(&String, &Untranslated)> {
+    let mut list: Vec<_> = tags.iter().collect();
+    list.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+    list
+}
+
+fn to_csv(tags: &HashMap<String, Untranslated>) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(["tag", "count", "example_url"])?;
+    for (tag, item) in sorted(tags) {
+        writer.write_record([tag, &item.count.to_string(), &item.example_url])?;
+    }
+    writer.flush()?;
+    Ok(writer.into_inner().unwrap_or_default())
+}
+
+// 按 EhTagTranslation 数据库中每个 namespace 一张表的格式输出，只填原始标签，
+// 其余列留给翻译者；出现次数和示例链接见 CSV
+fn to_markdown(tags: &HashMap<String, Untranslated>) -> String {
+    let mut namespaces: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (tag, _) in sorted(tags) {
+        let (namespace, name) = tag.split_once(':').unwrap_or(("misc", tag));
+        namespaces.entry(namespace).or_default().push(name);
+    }
+    let mut md = String::new();
+    for (namespace, names) in namespaces {
+        md.push_str(&format!("## {}\n\n", namespace));
+        md.push_str("| 原始标签 | 名称 | 描述 | 外部链接 |\n");
+        md.push_str("| -------- | ---- | ---- | -------- |\n");
+        for name in names {
+            md.push_str(&format!("| {} |  |  |  |\n", name.replace('|', "\\|")));
+        }
+        md.push('\n');
+    }
+    md
+}
+
+/// 导出本次运行收集到的未翻译标签，返回标签数量
+pub fn save_untranslated() -> Result<usize, Box<dyn std::error::Error>> {
+    let tags = untranslated_tags().lock().unwrap();
+    if tags.is_empty() {
+        return Ok(0);
+    }
+    write(UNTRANSLATED_CSV_NAME, to_csv(&tags)?)?;
+    write(UNTRANSLATED_MD_NAME, to_markdown(&tags))?;
+    Ok(tags.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> HashMap<String, Untranslated> {
+        let item = |count, url: &str| Untranslated {
+            count,
+            example_url: url.to_string(),
+        };
+        HashMap::from([
+            (
+                "female:a|b".to_string(),
+                item(1, "https://e-hentai.org/g/1/a/"),
+            ),
+            (
+                "female:zz".to_string(),
+                item(3, "https://e-hentai.org/g/2/b/"),
+            ),
+            ("male:c".to_string(), item(2, "https://e-hentai.org/g/3/c/")),
+        ])
+    }
+
+    #[test]
+    fn csv_sorted_by_count() {
+        let csv = String::from_utf8(to_csv(&tags()).unwrap()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "tag,count,example_url");
+        assert!(lines[1].starts_with("female:zz,3,"));
+        assert!(lines[3].starts_with("female:a|b,1,"));
+    }
+
+    #[test]
+    fn markdown_matches_upstream_columns() {
+        let md = to_markdown(&tags());
+        assert!(md.contains("## female\n"));
+        assert!(md.contains("| a\\|b |  |  |  |\n"));
+        assert!(md.contains("| zz |  |  |  |\n"));
+        assert!(!md.contains("出现"));
+        assert!(md.find("## female").unwrap() < md.find("## male").unwrap());
+    }
+}
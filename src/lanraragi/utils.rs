@@ -2,14 +2,70 @@ use super::error::FetchError;
 use clap::error::Result;
 
 use serde::de::DeserializeOwned;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+
+/// 限制请求频率，所有调用 `wait` 的任务共享同一个间隔
+pub struct RateLimiter {
+    next: Mutex<Option<Instant>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        Self {
+            next: Mutex::const_new(None),
+        }
+    }
+
+    /// 等到距离上一次请求至少 `interval` 后返回
+    pub async fn wait(&self, interval: Duration) {
+        let mut next = self.next.lock().await;
+        if let Some(at) = *next {
+            sleep_until(at).await;
+        }
+        *next = Some(Instant::now() + interval);
+    }
+}
 
 pub async fn fetch_raw_with_retry<F>(builder: F) -> Result<reqwest::Response, FetchError>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    retry(None, builder).await
+}
+
+/// 和 `fetch_raw_with_retry` 相同，但每次尝试前都先等待 `limiter`
+pub async fn fetch_limited_with_retry<F>(
+    limiter: &RateLimiter,
+    interval: Duration,
+    builder: F,
+) -> Result<reqwest::Response, FetchError>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    retry(Some((limiter, interval)), builder).await
+}
+
+async fn retry<F>(
+    limit: Option<(&RateLimiter, Duration)>,
+    builder: F,
+) -> Result<reqwest::Response, FetchError>
 where
     F: Fn() -> reqwest::RequestBuilder,
 {
     let mut retry_count = 0;
 
     loop {
+        if let Some((limiter, interval)) = limit {
+            limiter.wait(interval).await;
+        }
         match builder().send().await {
             Ok(response) if response.status().is_success() => {
                 return Ok(response);
@@ -40,3 +96,19 @@ where
         .json::<T>()
         .await?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rate_limiter_spaces_requests() {
+        let limiter = RateLimiter::new();
+        let interval = Duration::from_millis(50);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.wait(interval).await;
+        }
+        assert!(start.elapsed() >= interval * 2);
+    }
+}
@@ -1,62 +1,141 @@
-mod lanraragi;
+use lantag_c::lanraragi::apply::{apply_galleries, read_rows, ApplyRow};
+use lantag_c::lanraragi::archive::Archive;
+use lantag_c::lanraragi::args::{Cli, Command, ConfigAction};
+use lantag_c::lanraragi::calibrate::calibrate;
+use lantag_c::lanraragi::collect::{collect, review};
+use lantag_c::lanraragi::commands::{
+    doctor, load_dictionary, load_library, print_effective_dict, print_history, queue_command,
+    retry, revert, run, stats,
+};
+use lantag_c::lanraragi::config::Settings;
+use lantag_c::lanraragi::maintain::tags_command;
+use lantag_c::lanraragi::migrate::migrate_tags;
+use lantag_c::lanraragi::store::{approved_reviews, Decision};
+use lantag_c::lanraragi::tank::tank;
+use lantag_c::lanraragi::translate::translate_library;
+use lantag_c::lanraragi::untranslate::untranslate_library;
 
-use lanraragi::archive::Archive;
-use lanraragi::args::args;
-use lanraragi::tag::{fetch_latest_cn_tag, parse_data};
-use lanraragi::unhandle::add_and_save_no_handle;
+use clap::Parser;
 
-use owo_colors::OwoColorize;
-use tokio::task;
+async fn dispatch(cli: &Cli, settings: &Settings) -> Result<(), String> {
+    if cli.common.print_effective_dict {
+        let (_, tag_cn) = load_dictionary(settings).await?;
+        print_effective_dict(&tag_cn);
+        return Ok(());
+    }
+    match &cli.command {
+        None => run(settings, &cli.run).await,
+        Some(Command::Run(options)) => run(settings, options).await,
+        Some(Command::Retry(options)) => retry(settings, options).await,
+        Some(Command::Queue { action, options }) => queue_command(settings, action, options).await,
+        Some(Command::Apply {
+            arcid,
+            url,
+            file,
+            approved,
+        }) => {
+            if *approved {
+                let rows: Vec<ApplyRow> = approved_reviews()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (arcid, url))| ApplyRow {
+                        line: i + 1,
+                        arcid,
+                        url,
+                    })
+                    .collect();
+                return apply_galleries(settings, &rows, Decision::Approved).await;
+            }
+            let rows = match (file, arcid, url) {
+                (Some(path), _, _) => read_rows(path)?,
+                (None, Some(arcid), Some(url)) => vec![ApplyRow {
+                    line: 1,
+                    arcid: arcid.clone(),
+                    url: url.clone(),
+                }],
+                _ => unreachable!(),
+            };
+            apply_galleries(settings, &rows, Decision::Manual).await
+        }
+        Some(Command::Collect { jobs, select }) => collect(settings, *jobs, select).await,
+        Some(Command::Review { show_intro }) => review(settings, *show_intro).await,
+        Some(Command::Translate { dry_run }) => {
+            settings.require(false)?;
+            let (all_archive, _, tag_cn) = load_library(settings).await?;
+            translate_library(settings, &all_archive, &tag_cn, *dry_run).await;
+            Ok(())
+        }
+        Some(Command::Untranslate { arcid, dry_run }) => {
+            settings.require(false)?;
+            let (all_archive, _, tag_cn) = load_library(settings).await?;
+            untranslate_library(settings, &all_archive, &tag_cn, arcid.as_deref(), *dry_run).await;
+            Ok(())
+        }
+        Some(Command::MigrateTags { from, dry_run }) => {
+            settings.require(false)?;
+            let (all_archive, dict, tag_cn) = load_library(settings).await?;
+            migrate_tags(
+                settings,
+                &all_archive,
+                &dict.to_cn_map(),
+                &tag_cn,
+                &dict.head,
+                from.as_deref(),
+                *dry_run,
+            )
+            .await;
+            Ok(())
+        }
+        Some(Command::Tags { action }) => {
+            settings.require(false)?;
+            let all_archive = Archive::fetch_archives(settings).await;
+            tags_command(settings, &all_archive, action).await;
+            Ok(())
+        }
+        Some(Command::Revert {
+            batch,
+            arcid,
+            options,
+        }) => revert(settings, batch.as_deref(), arcid.as_deref(), options).await,
+        Some(Command::Tank { parents, options }) => {
+            settings.require(*parents)?;
+            let all_archive = Archive::fetch_archives(settings).await;
+            tank(settings, &all_archive, *parents, options).await
+        }
+        Some(Command::History { arcid }) => {
+            print_history(arcid);
+            Ok(())
+        }
+        Some(Command::Calibrate { precision }) => calibrate(&settings.scoring, *precision),
+        Some(Command::Stats) => stats(settings).await,
+        Some(Command::Doctor) => {
+            if doctor(settings).await {
+                Ok(())
+            } else {
+                Err("检查未通过".to_string())
+            }
+        }
+        Some(Command::Config {
+            action: ConfigAction::Show,
+        }) => {
+            settings.print();
+            Ok(())
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    args();
-    println!("获取Lanraragi作品和最新的cn标签...");
-    let fetch_task = task::spawn(Archive::fetch_archives());
-    let tags_task = task::spawn(fetch_latest_cn_tag());
-
-    // 等待两个任务完成
-    let all_archive = fetch_task.await.unwrap();
-    let tags = tags_task.await.unwrap();
-
-    // multi_progress().clear().unwrap();
-
-    let tag_cn = match parse_data(&tags) {
-        Ok(data) => data,
+    let cli = Cli::parse();
+    let settings = match Settings::resolve(&cli.common) {
+        Ok(settings) => settings,
         Err(err) => {
-            println!("获取标签失败: {}", err);
-            return;
+            eprintln!("{}", err);
+            std::process::exit(2);
         }
     };
-    let mut run_count = 0;
-
-    println!("共有 {} 条作品", all_archive.len().bright_green());
-    for archive in all_archive.iter() {
-        run_count += 1;
-        if !archive.is_empty_tags() {
-            continue;
-        }
-        let gls = archive.search_from_eh().await;
-        if gls.is_empty() {
-            add_and_save_no_handle(archive.clone());
-            continue;
-        }
-        let index = archive.print_and_get_index(&gls);
-        if index > 0 && index <= gls.len() as i32 {
-            let tags = &gls[index as usize - 1].get_tags_from_eh_gl(&tag_cn).await;
-            archive
-                .change_tags_to_lanraragi(&format!("{},{}", &archive.tags, &tags))
-                .await;
-            println!(
-                "已处理 {}/{}  {:.1}%",
-                run_count.green(),
-                all_archive.len().cyan(),
-                (run_count as f32 / all_archive.len() as f32 * 100.0).bright_green()
-            );
-        } else {
-            add_and_save_no_handle(archive.clone());
-            print!("❌not handle");
-        }
+    if let Err(err) = dispatch(&cli, &settings).await {
+        eprintln!("{}", err);
+        std::process::exit(1);
     }
-    println!("结束");
 }